@@ -1,14 +1,16 @@
 use core::fmt;
-use std::{collections::BTreeMap, rc::Rc};
+use std::{cmp, hash, mem, rc::Rc};
 
-use char_classes::any;
-use crate::Term;
+use crate::{Span, Term};
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Lambda expression, the spans do not take part in the comparison
+#[derive(Debug, Clone)]
 pub enum Atom {
     Term(Term),
-    Call(Rc<Atom>, Rc<Atom>),
-    Func(Term, Rc<Atom>),
+    Call(Rc<Atom>, Rc<Atom>, Span),
+    Func(Term, Rc<Atom>, Span),
+    /// Use of a named definition, the body is shared by every use
+    Ref(Term, Rc<Atom>),
 }
 
 impl Atom {
@@ -28,12 +30,81 @@ impl Atom {
         matches!(self, Self::Func(..))
     }
 
+    /// Span of the atom, [`Ref`] is the span of the use
+    ///
+    /// [`Ref`]: Atom::Ref
+    #[must_use]
+    pub fn span(&self) -> Span {
+        match self {
+            Atom::Term(term) | Atom::Ref(term, _) => term.1,
+            Atom::Call(_, _, span) | Atom::Func(_, _, span) => *span,
+        }
+    }
+
+    /// Nodes from self to the innermost node at `offset`,
+    /// the [`Ref`]s in the path are the expansion chain of inlined definitions
+    ///
+    /// [`Ref`]: Atom::Ref
+    #[must_use]
+    pub fn path_at(&self, offset: usize) -> Vec<&Atom> {
+        let mut path = vec![self];
+        let mut atom = self;
+        loop {
+            let next = match atom {
+                Atom::Term(_) => None,
+                Atom::Ref(term, _) if term.1.contains(offset) => None,
+                Atom::Ref(_, sub) | Atom::Func(_, sub, _) => {
+                    Some(&**sub).filter(|sub| sub.covers(offset))
+                },
+                Atom::Call(fun, a, _) => [fun, a].into_iter()
+                    .map(|sub| &**sub)
+                    .find(|sub| sub.covers(offset)),
+            };
+            match next {
+                Some(sub) => {
+                    path.push(sub);
+                    atom = sub;
+                },
+                None => break path,
+            }
+        }
+    }
+
+    /// Any node in self (include the definition bodies) span contains `offset`
+    fn covers(&self, offset: usize) -> bool {
+        self.span().contains(offset) || match self {
+            Atom::Term(_) => false,
+            Atom::Call(fun, a, _) => fun.covers(offset) || a.covers(offset),
+            Atom::Func(_, sub, _) | Atom::Ref(_, sub) => sub.covers(offset),
+        }
+    }
+
+    /// Returns `true` if the atom is [`Ref`].
+    ///
+    /// [`Ref`]: Atom::Ref
+    #[must_use]
+    pub fn is_ref(&self) -> bool {
+        matches!(self, Self::Ref(..))
+    }
+
+    /// Skip all [`Ref`] wrappers, get the inlined atom
+    ///
+    /// [`Ref`]: Atom::Ref
+    #[must_use]
+    pub fn unref(&self) -> &Atom {
+        match self {
+            Atom::Ref(_, atom) => atom.unref(),
+            _ => self,
+        }
+    }
+
     #[must_use]
     pub fn is_func_left(&self) -> bool {
         match self {
             Atom::Term(_) => false,
             Atom::Func(..) => true,
-            Atom::Call(left, _) => left.is_func_left(),
+            Atom::Call(left, _, _) => left.is_func_left(),
+            Atom::Ref(_, atom) => atom.is_func_left(),
         }
     }
 
@@ -42,7 +113,8 @@ impl Atom {
         match self {
             Atom::Term(_) => false,
             Atom::Func(..) => true,
-            Atom::Call(_, right) => right.is_func_right(),
+            Atom::Call(_, right, _) => right.is_func_right(),
+            Atom::Ref(_, atom) => atom.is_func_right(),
         }
     }
 
@@ -54,17 +126,45 @@ impl Atom {
         matches!(self, Self::Term(..))
     }
 
+    /// Free terms of the atom, deduplicated in order of first occurrence
+    #[must_use]
+    pub fn free_terms(&self) -> Vec<Term> {
+        fn walk(atom: &Atom, bound: &mut Vec<Term>, out: &mut Vec<Term>) {
+            match atom {
+                Atom::Term(t) => {
+                    if !bound.contains(t) && !out.contains(t) {
+                        out.push(t.clone());
+                    }
+                },
+                Atom::Call(fun, a, _) => {
+                    walk(fun, bound, out);
+                    walk(a, bound, out);
+                },
+                Atom::Func(p, e, _) => {
+                    bound.push(p.clone());
+                    walk(e, bound, out);
+                    bound.pop();
+                },
+                Atom::Ref(_, atom) => walk(atom, bound, out),
+            }
+        }
+        let mut out = vec![];
+        walk(self, &mut vec![], &mut out);
+        out
+    }
+
     fn simple(&self, level: usize) -> bool {
         if level == 0 {
             return false;
         }
         match self {
             Atom::Term(_) => true,
-            Atom::Func(_, atom) => atom.simple(level-1),
-            Atom::Call(a, b) => {
+            Atom::Func(_, atom, _) => atom.simple(level-1),
+            Atom::Call(a, b, _) => {
                 let next = level.saturating_sub(2);
                 a.simple(next) && b.simple(next)
             },
+            Atom::Ref(_, atom) => atom.simple(level),
         }
     }
 
@@ -79,7 +179,7 @@ impl Atom {
         }
         match self {
             Atom::Term(ch) => write!(f, "{ch}"),
-            Atom::Call(fun, a) => {
+            Atom::Call(fun, a, _) => {
                 ind.push_str("  ");
                 write!(f, "(\n{ind}")?;
                 fun.indented_fmt(ind, level, f)?;
@@ -89,7 +189,7 @@ impl Atom {
                 ind.pop(); ind.pop();
                 write!(f, "\n{ind})")
             },
-            Atom::Func(p, e) => {
+            Atom::Func(p, e, _) => {
                 ind.push_str("  ");
                 write!(f, "(λ{p}.\n{ind}")?;
 
@@ -98,6 +198,57 @@ impl Atom {
                 ind.pop(); ind.pop();
                 write!(f, "\n{ind})")
             },
+            Atom::Ref(_, atom) => atom.indented_fmt(ind, level, f),
+        }
+    }
+}
+
+impl PartialEq for Atom {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Atom::Term(a), Atom::Term(b)) => a == b,
+            (Atom::Call(f, a, _), Atom::Call(g, b, _)) => f == g && a == b,
+            (Atom::Func(p, e, _), Atom::Func(q, d, _)) => p == q && e == d,
+            (Atom::Ref(n, a), Atom::Ref(m, b)) => n == m && a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Atom { }
+
+impl Ord for Atom {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        let rank = |atom: &Atom| match atom {
+            Atom::Term(_) => 0,
+            Atom::Call(..) => 1,
+            Atom::Func(..) => 2,
+            Atom::Ref(..) => 3,
+        };
+        match (self, other) {
+            (Atom::Term(a), Atom::Term(b)) => a.cmp(b),
+            (Atom::Call(f, a, _), Atom::Call(g, b, _)) => f.cmp(g).then_with(|| a.cmp(b)),
+            (Atom::Func(p, e, _), Atom::Func(q, d, _)) => p.cmp(q).then_with(|| e.cmp(d)),
+            (Atom::Ref(n, a), Atom::Ref(m, b)) => n.cmp(m).then_with(|| a.cmp(b)),
+            _ => rank(self).cmp(&rank(other)),
+        }
+    }
+}
+
+impl PartialOrd for Atom {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl hash::Hash for Atom {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        mem::discriminant(self).hash(state);
+        match self {
+            Atom::Term(term) => term.hash(state),
+            Atom::Call(fun, a, _) => (fun, a).hash(state),
+            Atom::Func(p, e, _) => (p, e).hash(state),
+            Atom::Ref(name, atom) => (name, atom).hash(state),
         }
     }
 }
@@ -108,12 +259,17 @@ impl From<Term> for Atom {
     }
 }
 
+/// Alternate (`{:#}`) keeps the names of definitions instead of inlining it
 impl fmt::Display for Atom {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Atom::Term(ch) => write!(f, "{ch}"),
-            Atom::Call(fun, a) => write!(f, "({fun}{a})"),
-            Atom::Func(p, e) => write!(f, "(λ{p}.{e})"),
+            Atom::Call(fun, a, _) if f.alternate() => write!(f, "({fun:#}{a:#})"),
+            Atom::Call(fun, a, _) => write!(f, "({fun}{a})"),
+            Atom::Func(p, e, _) if f.alternate() => write!(f, "(λ{p}.{e:#})"),
+            Atom::Func(p, e, _) => write!(f, "(λ{p}.{e})"),
+            Atom::Ref(name, _) if f.alternate() => write!(f, "{name}"),
+            Atom::Ref(_, atom) => fmt::Display::fmt(&**atom, f),
         }
     }
 }
@@ -125,76 +281,21 @@ impl fmt::Octal for Atom {
         }
         match self {
             Atom::Term(ch) => write!(f, "{ch}"),
-            Atom::Call(fun, a) => {
+            Atom::Call(fun, a, _) => {
                 if f.precision().is_some_and(|p| p<=2) {
                     write!(f, "{:.2o}{:.3o}", **fun, **a)
                 } else {
                     write!(f, "({:.2o}{:.3o})", **fun, **a)
                 }
             },
-            Atom::Func(p, e) => {
+            Atom::Func(p, e, _) => {
                 if f.precision() == Some(1) {
                     write!(f, "λ{p}.{:.1o}", **e)
                 } else {
                     write!(f, "(λ{p}.{:.1o})", **e)
                 }
             },
+            Atom::Ref(_, atom) => fmt::Octal::fmt(&**atom, f),
         }
     }
 }
-
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
-struct Ctx {
-    defines: BTreeMap<Term, Vec<Atom>>,
-}
-
-peg::parser!(grammar parser(ctx: &mut Ctx) for str {
-    rule _() = quiet!{[ch if any!(" \t\r\n", ch)]*} comment()?
-    rule comment() = quiet!{"#" [^'\r' | '\n']* _}
-    rule term() -> Term
-        = p:position!()
-          t:quiet!{
-            ch:[ch if any!("a-zA-Z$_Σ-κμ-ϧ+*/%!-", ch)]
-            { Term(ch.encode_utf8(&mut [0, 0]).into(), p) }
-            / "`" s:$(([^ch if any!(" \t\r\n`", ch)])+) "`"
-            { Term(s.into(), p) }
-          }
-        {t}
-        / expected!("term")
-    rule lambda() = "λ" / "^"
-
-    rule expr_definite() -> Atom
-        = name:term() _ ":=" _ e:expr_lambda() _ ";" _
-          ({ ctx.defines.entry(name.clone()).or_default().push(e); })
-          e:expr_definite()
-        {
-            ctx.defines.get_mut(&name).unwrap().pop().unwrap();
-            e
-        }
-        / expr_lambda()
-    rule expr_lambda() -> Atom
-        = (lambda() _)? names:term()++_ _ "." _ sub:expr_lambda()
-        { names.into_iter().rfold(sub, |sub, name| {
-            Atom::Func(name, sub.into())
-        }) }
-        / expr_call()
-    rule expr_call() -> Atom
-        = atoms:expr_atom()++_
-        { atoms.into_iter().reduce(|a, b| {
-            Atom::Call(a.into(), b.into())
-        }).unwrap() }
-    rule expr_atom() -> Atom
-        = t:term()
-        { ctx.defines.get(&t)
-            .and_then(|vec| vec.last().cloned())
-            .unwrap_or(t.into()) }
-        / "(" _ e:expr_definite() _  ")" { e }
-    pub rule expr() -> Atom
-        = _ e:expr_definite() _ { e }
-});
-
-pub type PegResult<T> = Result<T, peg::error::ParseError<peg::str::LineCol>>;
-
-pub fn expr(s: &str) -> PegResult<Atom> {
-    parser::expr(s, &mut Default::default())
-}
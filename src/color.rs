@@ -0,0 +1,63 @@
+use std::str::FromStr;
+
+use crate::GraphCtx;
+
+/// Distinct colors for the coloring modes
+pub const PALETTE: [[u8; 3]; 8] = [
+    [0x1f, 0x77, 0xb4],
+    [0xff, 0x7f, 0x0e],
+    [0x2c, 0xa0, 0x2c],
+    [0xd6, 0x27, 0x28],
+    [0x94, 0x67, 0xbd],
+    [0x8c, 0x56, 0x4b],
+    [0xe3, 0x77, 0xc2],
+    [0x17, 0xbe, 0xcf],
+];
+
+/// What the colors of the cells are chosen by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Coloring {
+    /// Each abstraction with the wires of its variable
+    Binder,
+    /// Count of abstractions enclosing the binder
+    BinderDepth,
+    /// Depth of the drawn subterm
+    NodeDepth,
+}
+
+impl FromStr for Coloring {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "binder" => Coloring::Binder,
+            "binder-depth" => Coloring::BinderDepth,
+            "depth" | "node-depth" => Coloring::NodeDepth,
+            _ => return Err(format!("unknown coloring `{s}`")),
+        })
+    }
+}
+
+/// Nearest color of the xterm 256 colors cube
+pub fn ansi256([r, g, b]: [u8; 3]) -> u8 {
+    let level = |c: u8| ((u16::from(c) * 5 + 127) / 255) as u8;
+    16 + 36 * level(r) + 6 * level(g) + level(b)
+}
+
+impl GraphCtx {
+    /// Set the colors of the drawn cells from the palette,
+    /// the application and box cells keep the foreground by binder coloring
+    pub fn paint(&mut self, coloring: Coloring, palette: &[[u8; 3]]) {
+        if palette.is_empty() {
+            return;
+        }
+        for cell in self.screen.cells_mut() {
+            let index = match coloring {
+                Coloring::Binder => cell.binder,
+                Coloring::BinderDepth => cell.binder.map(|binder| self.binders[binder].depth),
+                Coloring::NodeDepth => Some(self.nodes[cell.node].depth),
+            };
+            cell.color = index.map(|i| palette[i % palette.len()]);
+        }
+    }
+}
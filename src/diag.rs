@@ -0,0 +1,107 @@
+use core::fmt;
+
+use unicode_width::UnicodeWidthStr;
+
+use crate::{ParseError, Source};
+
+/// Report with source snippet
+///
+/// ```text
+/// error: expected one of `(`, `.`, `;`, term
+///  --> lib.lam:2:3
+///   |
+/// 2 | I := x.x;
+///   |   ^^
+/// ```
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub level: &'static str,
+    pub message: String,
+    pub source: Source,
+    /// Byte range in the source
+    pub span: (usize, usize),
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, source: &Source, span: (usize, usize)) -> Self {
+        Self {
+            level: "error",
+            message: message.into(),
+            source: source.clone(),
+            span,
+            notes: vec![],
+        }
+    }
+
+    pub fn note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(e: &ParseError) -> Self {
+        let (source, start) = (e.source(), e.offset());
+        let message = match e {
+            ParseError::Syntax(_, e) => {
+                let mut tokens = e.expected.tokens()
+                    .map(|token| token.strip_prefix('"')
+                        .and_then(|s| s.strip_suffix('"'))
+                        .map_or_else(|| token.to_owned(), |s| {
+                            format!("`{}`", s.replace("\\\"", "\"").replace("\\\\", "\\"))
+                        }))
+                    .collect::<Vec<_>>();
+                tokens.sort();
+                match &tokens[..] {
+                    [] => "unexpected input".into(),
+                    [token] => format!("expected {token}"),
+                    _ => format!("expected one of {}", tokens.join(", ")),
+                }
+            },
+            ParseError::Import(_, _, path, e) => {
+                format!("cannot import {:?}: {e}", path.display())
+            },
+            ParseError::ImportCycle(_, _, path) => {
+                format!("import cycle on {:?}", path.display())
+            },
+            ParseError::Case(_, _, message) => message.clone(),
+        };
+        let len = match e {
+            ParseError::Case(_, span, _) => span.end - span.start,
+            _ => source.text.get(start..).unwrap_or_default()
+                .split(char::is_whitespace)
+                .next()
+                .map_or(0, str::len),
+        };
+        Self::error(message, source, (start, start+len))
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = &*self.source.text;
+        let (start, end) = (self.span.0.min(text.len()), self.span.1.min(text.len()));
+        let (line, col) = self.source.line_column(start);
+
+        let line_start = text[..start].rfind('\n').map_or(0, |i| i+1);
+        let line_end = text[start..].find('\n').map_or(text.len(), |i| start+i);
+        let line_text = text[line_start..line_end].trim_end_matches('\r');
+        let end = end.clamp(start, line_start + line_text.len());
+
+        let lineno = line.to_string();
+        let pad = " ".repeat(lineno.len());
+        let indent = text[line_start..start].replace('\t', " ").width();
+        let marks = text[start..end].width().max(1);
+
+        writeln!(f, "{}: {}", self.level, self.message)?;
+        writeln!(f, "{pad}--> {}:{line}:{col}", self.source.name())?;
+        writeln!(f, "{pad} |")?;
+        writeln!(f, "{lineno} | {}", line_text.replace('\t', " "))?;
+        write!(f, "{pad} | {:indent$}{}", "", "^".repeat(marks))?;
+        for note in &self.notes {
+            write!(f, "\n{pad} = note: {note}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+use std::{collections::{BTreeMap, BTreeSet, HashMap}, path::Path, rc::Rc};
+
+use unicode_width::UnicodeWidthStr;
+
+use crate::{parser::{Assoc, Fixity}, Atom, Doc, Minimal, ParseError, Sources, Span, Term};
+
+/// Top level statement of the source
+#[derive(Debug, Clone)]
+pub(crate) enum Item {
+    Define(Term, Rc<Atom>),
+    Infix(Fixity, Term, Rc<Atom>),
+    /// Names with the written bodies
+    Rec(Vec<(Term, Atom)>),
+    /// Data name, constructors with the fields, and the constructor bodies
+    Data(Term, Vec<(Term, Vec<Term>)>, Vec<Rc<Atom>>),
+    Import(Rc<str>),
+    Expr(Atom),
+}
+
+/// Statements and comments of the source, positions are local
+#[derive(Debug, Default)]
+pub(crate) struct Program {
+    pub items: Vec<(Span, Item)>,
+    pub comments: BTreeMap<usize, Rc<str>>,
+    /// Starts of the literals and `case` expressions
+    pub written: BTreeSet<usize>,
+    pub idents: bool,
+}
+
+impl Program {
+    /// The statement has nested definitions, comments or sugar,
+    /// which the atoms can not rebuild
+    fn as_written(&self, span: Span) -> bool {
+        let inner = span.start+1..span.end;
+        self.items.iter().any(|(item, _)| inner.contains(&item.start))
+            || self.comments.range(span.start..span.end).next().is_some()
+            || self.written.range(span.start..span.end).next().is_some()
+    }
+}
+
+/// Statements on the same or next line,
+/// only whitespace and an optional comment to the end of line between
+fn next_line(gap: &str) -> bool {
+    let mut lines = gap.split('\n');
+    let first = lines.next().unwrap_or_default().trim();
+    let rest = lines.next().unwrap_or_default();
+    (first.is_empty() || first.starts_with('#'))
+        && rest.trim().is_empty()
+        && lines.next().is_none()
+}
+
+struct Formatter<'a> {
+    program: &'a Program,
+    ops: HashMap<*const Atom, Fixity>,
+    lines: Vec<String>,
+}
+
+impl Formatter<'_> {
+    /// Comments and blank lines between statements
+    fn gap(&mut self, gap: &str, has_prev: bool) {
+        let parts = gap.split('\n').collect::<Vec<_>>();
+        let mut blank = false;
+        for (i, part) in parts.iter().enumerate() {
+            let part = part.trim();
+            if i == 0 && has_prev {
+                if !part.is_empty() {
+                    let last = self.lines.last_mut().unwrap();
+                    *last += " ";
+                    *last += part;
+                }
+            } else if part.is_empty() {
+                blank |= i != 0 && i+1 != parts.len();
+            } else {
+                if blank && !self.lines.is_empty() {
+                    self.lines.push(String::new());
+                }
+                blank = false;
+                self.lines.push(part.to_owned());
+            }
+        }
+        if blank && !self.lines.is_empty() {
+            self.lines.push(String::new());
+        }
+    }
+
+    fn minimal<'b>(&'b self, atom: &'b Atom) -> Minimal<'b> {
+        Minimal::new(atom).idents(self.program.idents).refs(true).ops(&self.ops)
+    }
+
+    fn doc(&self, atom: &Atom) -> Doc {
+        self.minimal(atom).doc()
+    }
+
+    fn define(&self, name: &Term, body: &Atom, pad: usize) -> Doc {
+        let name = self.minimal(body).term(name);
+        let pad = pad.saturating_sub(name.width());
+        Doc::text(format!("{name}{:pad$} := ", ""))
+            .cat(self.doc(body).nest(2))
+    }
+
+    fn item(&self, item: &Item, pad: usize) -> Doc {
+        match item {
+            Item::Define(name, body) => self.define(name, body, pad).cat(Doc::text(";")),
+            Item::Infix(Fixity { assoc, prec }, name, body) => {
+                let assoc = match assoc {
+                    Assoc::Left => "infixl",
+                    Assoc::Right => "infixr",
+                    Assoc::None => "infix",
+                };
+                Doc::text(format!("{assoc} {prec} "))
+                    .cat(self.define(name, body, 0))
+                    .cat(Doc::text(";"))
+            },
+            Item::Rec(defs) => {
+                let defs = defs.iter().enumerate().fold(Doc::Nil, |doc, (i, (name, body))| {
+                    let sep = if i == 0 { Doc::Nil } else { Doc::text(",").cat(Doc::Line(" ")) };
+                    doc.cat(sep).cat(self.define(name, body, 0))
+                });
+                Doc::text("rec ").cat(defs.nest(4)).group().cat(Doc::text(";"))
+            },
+            Item::Data(name, ctors, bodies) => {
+                let term = |term| self.minimal(&bodies[0]).term(term);
+                let ctors = ctors.iter()
+                    .map(|(ctor, fields)| {
+                        let fields = fields.iter().map(|field| format!(" {}", term(field)));
+                        term(ctor) + &fields.collect::<String>()
+                    })
+                    .collect::<Vec<_>>();
+                Doc::text(format!("data {} = {};", term(name), ctors.join(" | ")))
+            },
+            Item::Import(path) => Doc::text(format!("import \"{path}\";")),
+            Item::Expr(atom) => self.doc(atom),
+        }
+    }
+}
+
+impl Sources {
+    /// Re-emit the source with normalized spacing, aligned `:=` of consecutive definitions
+    /// and the bodies wrapped into `width`, keep the `#` comments
+    ///
+    /// Statements with nested definitions, inner comments, literals or `case`
+    /// are kept as written
+    pub fn format(
+        &mut self,
+        path: Option<&Path>,
+        s: &str,
+        width: usize,
+    ) -> Result<String, ParseError> {
+        let program = self.parse_program(path, s)?;
+
+        let mut items = Vec::<&(Span, Item)>::new();
+        for item in &program.items {
+            if items.last().is_none_or(|last| last.0.end <= item.0.start) {
+                items.push(item);
+            }
+        }
+        let written = items.iter()
+            .map(|(span, _)| program.as_written(*span))
+            .collect::<Vec<_>>();
+        let ops = items.iter()
+            .filter_map(|(_, item)| match item {
+                Item::Infix(fixity, _, body) => Some((Rc::as_ptr(body), *fixity)),
+                _ => None,
+            })
+            .collect();
+        let mut fmt = Formatter { program: &program, ops, lines: vec![] };
+
+        // align `:=` in the runs of definitions on consecutive lines
+        let mut pads = vec![0; items.len()];
+        let mut run_start = 0;
+        let define = |i: usize| matches!(items[i].1, Item::Define(..)) && !written[i];
+        for i in 0..=items.len() {
+            let continued = i != 0 && i != items.len()
+                && define(i) && define(i-1)
+                && next_line(&s[items[i-1].0.end..items[i].0.start]);
+            if continued {
+                continue;
+            }
+            let pad = items[run_start..i].iter()
+                .filter_map(|(_, item)| match item {
+                    Item::Define(name, body) => Some(fmt.minimal(body).term(name).width()),
+                    _ => None,
+                })
+                .max()
+                .unwrap_or(0);
+            pads[run_start..i].fill(pad);
+            run_start = i;
+        }
+
+        let mut prev_end = 0;
+        for ((&(span, ref item), pad), written) in items.iter().copied().zip(pads).zip(written) {
+            fmt.gap(&s[prev_end..span.start], prev_end != 0);
+            if written {
+                let text = &s[span.start..span.end];
+                fmt.lines.extend(text.lines().map(|line| line.trim_end().to_owned()));
+            } else {
+                let text = fmt.item(item, pad).render(width);
+                fmt.lines.extend(text.lines().map(str::to_owned));
+            }
+            prev_end = span.end;
+        }
+        fmt.gap(&s[prev_end..], prev_end != 0);
+
+        while fmt.lines.last().is_some_and(String::is_empty) {
+            fmt.lines.pop();
+        }
+        let mut out = fmt.lines.join("\n");
+        out.push('\n');
+        Ok(out)
+    }
+}
@@ -0,0 +1,94 @@
+use core::fmt;
+
+use unicode_width::UnicodeWidthStr;
+
+use crate::{CellKind, Screen};
+
+/// Box-drawing char by the set neighbours, bits are up, down, left, right
+const BOX: [char; 16] = [
+    '·', '╶', '╴', '─',
+    '╷', '┌', '┐', '┬',
+    '╵', '└', '┘', '┴',
+    '│', '├', '┤', '┼',
+];
+
+/// Half-block char by the top and bottom cells
+const HALF: [char; 4] = [' ', '▀', '▄', '█'];
+
+/// Braille dot bits of the 2x4 cells, by row then column
+const BRAILLE: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+impl Screen {
+    fn neighbours(&self, y: usize, x: usize) -> usize {
+        let up = y != 0 && self.get(y-1, x);
+        let left = x != 0 && self.get(y, x-1);
+        usize::from(up) << 3
+            | usize::from(self.get(y+1, x)) << 2
+            | usize::from(left) << 1
+            | usize::from(self.get(y, x+1))
+    }
+
+    /// Plain text with box-drawing chars, two columns per cell
+    pub fn print_box<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        for y in 0..self.height() {
+            let mut line = String::new();
+            let mut skip = 0;
+            for x in 0..self.width() {
+                if skip != 0 {
+                    skip -= 1;
+                    continue;
+                }
+                if let Some((text, cells)) = self.label_at(y, x, 2) {
+                    line += text;
+                    line += &" ".repeat(cells*2 - text.width());
+                    skip = cells - 1;
+                    continue;
+                }
+                if !self.get(y, x) {
+                    line += "  ";
+                    continue;
+                }
+                let mask = self.neighbours(y, x);
+                line.push(if self.cell(y, x).kind == CellKind::Arrow { '<' } else { BOX[mask] });
+                line.push(if mask & 1 == 1 { '─' } else { ' ' });
+            }
+            writeln!(w, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+
+    /// Pack the `rows` by `cols` cells into a char, labels are not drawn
+    fn print_dense<W: fmt::Write>(
+        &self,
+        w: &mut W,
+        rows: usize,
+        cols: usize,
+        glyph: impl Fn(usize, usize) -> u32,
+        to_char: impl Fn(u32) -> char,
+    ) -> fmt::Result {
+        for y in (0..self.height()).step_by(rows) {
+            let line = (0..self.width()).step_by(cols)
+                .map(|x| {
+                    let bits = (0..rows).flat_map(|dy| (0..cols).map(move |dx| (dy, dx)))
+                        .filter(|&(dy, dx)| self.get(y+dy, x+dx))
+                        .fold(0, |bits, (dy, dx)| bits | glyph(dy, dx));
+                    to_char(bits)
+                })
+                .collect::<String>();
+            writeln!(w, "{}", line.trim_end())?;
+        }
+        Ok(())
+    }
+
+    /// Half-blocks of the two vertical cells per char
+    pub fn print_half<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        self.print_dense(w, 2, 1, |dy, _| 1 << dy, |bits| HALF[bits as usize])
+    }
+
+    /// Braille dots of the 2x4 cells per char
+    pub fn print_braille<W: fmt::Write>(&self, w: &mut W) -> fmt::Result {
+        self.print_dense(w, 4, 2, |dy, dx| BRAILLE[dy][dx], |bits| {
+            if bits == 0 { ' ' } else { char::from_u32(0x2800 + bits).unwrap() }
+        })
+    }
+}
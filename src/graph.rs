@@ -1,17 +1,82 @@
-use std::mem::replace;
+use std::{collections::BTreeSet, mem::{replace, take}, rc::Rc, str::FromStr};
 
-use crate::{utils::MaxTo, Atom, Screen, Term};
+use unicode_width::UnicodeWidthStr;
+
+use crate::{utils::MaxTo, Atom, Cell, CellKind, Screen, Span, Term};
 
 #[derive(Debug, Clone)]
 pub enum Error {
     UndefinedTerm(Term),
+    /// Error in the body of the definition, used by the term
+    InDefinition(Term, Box<Error>),
 }
 
 #[derive(Debug)]
 struct Bar {
     name: Term,
+    binder: usize,
     y: usize,
     end: usize,
+    /// Index in a merged line, the wires start below a gap of that many rows
+    merged: usize,
+}
+
+impl Bar {
+    /// First row of the wires
+    fn top(&self) -> usize {
+        match self.merged {
+            0 => self.y,
+            gap => self.y + 1 + gap,
+        }
+    }
+}
+
+/// Atom drawn on the screen
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub span: Span,
+    /// Depth in the atom tree
+    pub depth: usize,
+}
+
+/// Variable bound by an abstraction
+#[derive(Debug, Clone)]
+pub struct Binder {
+    pub name: Term,
+    /// Node of the abstraction
+    pub node: usize,
+    /// Count of the enclosing abstractions
+    pub depth: usize,
+}
+
+/// Variant of the diagram layout
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    /// Variable wires extend down to the application line below the operands
+    #[default]
+    Standard,
+    /// Application lines connect the leftmost wires at the minimal depth,
+    /// a variable is ready below the line of its abstraction
+    Alternative,
+    /// Consecutive curried abstractions share one line,
+    /// the wires of each next binder start one row lower
+    Merged,
+    /// Application lines have an arrow head pointing to the function
+    Arrows,
+}
+
+impl FromStr for Style {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "standard" => Style::Standard,
+            "alternative" => Style::Alternative,
+            "merged" => Style::Merged,
+            "arrows" => Style::Arrows,
+            _ => return Err(format!("unknown style `{s}`")),
+        })
+    }
 }
 
 #[derive(Debug, Default)]
@@ -23,12 +88,39 @@ pub struct GraphCtx {
     offset_handle: usize,
     y: usize,
     fun_offset: Option<usize>,
+    /// Drawing the direct body of an abstraction
+    curried: bool,
+    /// First line the last output handle can be connected at
+    ready: usize,
+    pub style: Style,
     pub func_extra_unit: Option<usize>,
     pub call_extra_unit: usize,
+    /// Names of definitions draw as labelled box
+    pub boxes: BTreeSet<Rc<str>>,
+    /// Boxed definitions drawn, in order of first use
+    pub boxed: Vec<(Term, Rc<Atom>)>,
+    /// Drawn atoms, indexed by [`Cell::node`]
+    pub nodes: Vec<Node>,
+    /// Drawn binders, indexed by [`Cell::binder`]
+    pub binders: Vec<Binder>,
+    /// Label each variable wire by the name at the bottom end
+    pub wire_labels: bool,
+    depth: usize,
 }
 
 impl GraphCtx {
     pub fn foo(&mut self, atom: &Atom) -> Result<(), Error> {
+        let node = self.nodes.len();
+        self.nodes.push(Node { span: atom.span(), depth: self.depth });
+        self.depth += 1;
+        let result = self.draw(atom, node);
+        self.depth -= 1;
+        result
+    }
+
+    fn draw(&mut self, atom: &Atom, node: usize) -> Result<(), Error> {
+        let app = Cell::new(CellKind::App, node, None);
+        let curried = take(&mut self.curried);
         match atom {
             Atom::Term(term) => {
                 self.fun_offset = None;
@@ -44,9 +136,17 @@ impl GraphCtx {
                     bar.end.max_to(x+1)
                 });
                 let bar = &self.bars[hit];
-                self.screen.bar(bar.y, x, self.y-bar.y);
+                let wire = Cell::new(CellKind::Wire, node, Some(bar.binder));
+                self.ready = match self.style {
+                    Style::Alternative => bar.y+1,
+                    _ => self.y,
+                };
+                self.screen.bar(bar.top(), x, self.ready-bar.top(), wire);
+                if self.wire_labels {
+                    self.screen.label((self.ready-1).max(bar.top()+1), x, term.to_string());
+                }
             },
-            Atom::Call(fun, arg) => {
+            Atom::Call(fun, arg, _) => {
                 let base_y = self.y;
 
                 self.foo(fun)?;
@@ -60,23 +160,27 @@ impl GraphCtx {
                 }
 
                 let left_y = replace(&mut self.y, base_y);
-                let left_handle = self.offset_handle;
+                let (left_handle, left_ready) = (self.offset_handle, self.ready);
 
                 self.foo(arg)?;
-                let right_y = self.y;
-                let right_handle = self.offset_handle;
+                let (right_handle, right_ready) = (self.offset_handle, self.ready);
 
-                self.y = self.y.max(left_y);
-                self.screen.line(self.y, left_handle, self.offset_handle-left_handle+1);
+                let link = left_ready.max(right_ready);
+                self.screen.line(link, left_handle, right_handle-left_handle+1, app);
+                if self.style == Style::Arrows {
+                    let arrow = Cell::new(CellKind::Arrow, node, None);
+                    self.screen.line(link, left_handle+1, 1, arrow);
+                }
 
                 self.offset_handle = left_handle;
 
-                self.screen.bar(left_y, left_handle, self.y-left_y);
-                self.screen.bar(right_y, right_handle, self.y-right_y);
-                self.screen.bar(self.y, left_handle, 2);
-                self.y += 2;
+                self.screen.bar(left_ready, left_handle, link-left_ready, app);
+                self.screen.bar(right_ready, right_handle, link-right_ready, app);
+                self.screen.bar(link, left_handle, 2, app);
+                self.ready = link + 2;
+                self.y = self.y.max(left_y).max(self.ready);
             },
-            Atom::Func(name, atom) => {
+            Atom::Func(name, atom, _) => {
                 let is_leader = self.fun_offset.is_none();
                 if let Some(offset) = self.fun_offset.take() {
                     self.offset = offset;
@@ -86,16 +190,29 @@ impl GraphCtx {
                     self.leaders.push(self.bars.len());
                 }
 
-                let y = self.y;
+                let merged = match self.bars.last() {
+                    Some(outer) if self.style == Style::Merged && curried => outer.merged + 1,
+                    _ => 0,
+                };
+                let y = if merged != 0 { self.bars.last().unwrap().y } else { self.y };
                 let x = self.offset;
-                self.add_bar(name.clone(), y);
+                let binder = self.binders.len();
+                self.binders.push(Binder { name: name.clone(), node, depth: self.bars.len() });
+                self.add_bar(name.clone(), binder, y, merged);
 
                 self.fun_offset = Some(self.offset);
                 self.offset += 1;
-                self.y += 2;
+                self.y = y + 2 + merged;
 
+                self.curried = true;
                 self.foo(atom)?;
 
+                // output from the bottom of the body
+                let handle = self.offset_handle;
+                let above = self.screen.cell(self.ready-1, handle);
+                self.screen.bar(self.ready, handle, self.y-self.ready, above);
+                self.ready = self.y;
+
                 //self.sync_leader();
                 self.ext_end_from_subfunc();
 
@@ -107,9 +224,85 @@ impl GraphCtx {
                 }
 
                 self.offset.max_to(end+self.func_extra_unit.unwrap_or(0));
-                self.screen.line(y, x, end-x+1);
+                let lambda = Cell::new(CellKind::Lambda, node, Some(binder));
+                self.screen.line(y, x, end-x+1, lambda);
+            },
+            Atom::Ref(name, atom) if self.boxes.contains(&name.0) => {
+                self.draw_box(name, atom, node).map_err(|e| {
+                    Error::InDefinition(name.clone(), e.into())
+                })?;
             },
+            Atom::Ref(name, atom) => {
+                self.curried = curried;
+                self.foo(atom).map_err(|e| {
+                    Error::InDefinition(name.clone(), e.into())
+                })?;
+            },
+        }
+        Ok(())
+    }
+
+    /// Label the rows of abstractions by the binder names, in a column reserved at the left
+    pub fn label_binders(&mut self) {
+        let mut lambdas = self.screen.segments().iter()
+            .filter(|seg| seg.cell.kind == CellKind::Lambda)
+            .map(|seg| (seg.y, seg.x, seg.cell.binder.unwrap()))
+            .collect::<Vec<_>>();
+        lambdas.sort_unstable();
+        let mut rows = vec![String::new(); self.screen.height()];
+        for (y, _, binder) in lambdas {
+            if !rows[y].is_empty() {
+                rows[y].push(' ');
+            }
+            rows[y] += &self.binders[binder].name.0;
+        }
+        let width = rows.iter().map(|names| names.width()).max().unwrap_or(0);
+        if width == 0 {
+            return;
+        }
+        self.screen.indent(width + 1);
+        for (y, names) in rows.into_iter().enumerate().filter(|(_, names)| !names.is_empty()) {
+            self.screen.label(y, 0, names);
+        }
+    }
+
+    /// Draw a box with name, free terms input from top, output from bottom left
+    fn draw_box(&mut self, name: &Term, atom: &Rc<Atom>, node: usize) -> Result<(), Error> {
+        let inputs = atom.free_terms();
+        let (x, y) = (self.offset, self.y);
+        let width = (name.width() + 2)
+            .max(inputs.len()*2 + 1)
+            .max(3);
+
+        for (i, term) in inputs.iter().enumerate() {
+            let hit = self.bars.iter()
+                .rposition(|bar| bar.name == *term)
+                .ok_or_else(|| Error::UndefinedTerm(term.clone()))?;
+            let input = x+1+i*2;
+            let bar = &mut self.bars[hit];
+            bar.end.max_to(input+1);
+            let wire = Cell::new(CellKind::Wire, node, Some(bar.binder));
+            self.screen.bar(bar.top(), input, y-bar.top(), wire);
+            if self.wire_labels {
+                self.screen.label(y-1, input, term.to_string());
+            }
+        }
+
+        let frame = Cell::new(CellKind::Box, node, None);
+        self.screen.line(y, x, width, frame);
+        self.screen.bar(y, x, 4, frame);
+        self.screen.bar(y, x+width-1, 3, frame);
+        self.screen.line(y+2, x, width, frame);
+        self.screen.label(y+1, x+1, name.to_string());
+
+        if !self.boxed.iter().any(|(boxed, _)| boxed == name) {
+            self.boxed.push((name.clone(), atom.clone()));
         }
+        self.fun_offset = None;
+        self.offset_handle = x;
+        self.offset = x + width-1;
+        self.y += 4;
+        self.ready = self.y;
         Ok(())
     }
 
@@ -133,11 +326,13 @@ impl GraphCtx {
         });
     }
 
-    fn add_bar(&mut self, name: Term, y: usize) {
+    fn add_bar(&mut self, name: Term, binder: usize, y: usize, merged: usize) {
         self.bars.push(Bar {
             name,
+            binder,
             y,
             end: self.offset,
+            merged,
         });
     }
 }
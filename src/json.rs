@@ -0,0 +1,82 @@
+use core::fmt;
+
+use crate::{Binder, CellKind, Node, Screen};
+
+pub struct JsonCtx<'a, W> {
+    pub writer: W,
+    /// Nodes of [`Cell::node`](crate::Cell::node)
+    pub nodes: &'a [Node],
+    /// Binders of [`Cell::binder`](crate::Cell::binder)
+    pub binders: &'a [Binder],
+}
+
+fn string(s: &str) -> String {
+    let mut out = String::from('"');
+    for ch in s.chars() {
+        match ch {
+            '"' => out += "\\\"",
+            '\\' => out += "\\\\",
+            '\n' => out += "\\n",
+            ch if ch < ' ' => out += &format!("\\u{:04x}", u32::from(ch)),
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn kind(kind: CellKind) -> &'static str {
+    match kind {
+        CellKind::Empty => "empty",
+        CellKind::Lambda => "lambda",
+        CellKind::App => "app",
+        CellKind::Wire => "wire",
+        CellKind::Box => "box",
+        CellKind::Arrow => "arrow",
+    }
+}
+
+impl Screen {
+    /// Segments with the end points in cells, the nodes, binders and labels as JSON
+    pub fn print_json<W: fmt::Write>(&self, ctx: &mut JsonCtx<'_, W>) -> fmt::Result {
+        let JsonCtx { writer: ref mut w, nodes, binders } = *ctx;
+        let sep = |i: usize| if i == 0 { "" } else { "," };
+
+        writeln!(w, r#"{{"width":{},"height":{},"segments":["#, self.width(), self.height())?;
+        for (i, seg) in self.segments().iter().enumerate() {
+            let (y2, x2) = if seg.vertical {
+                (seg.y + seg.len-1, seg.x)
+            } else {
+                (seg.y, seg.x + seg.len-1)
+            };
+            let binder = seg.cell.binder.map_or("null".into(), |b| b.to_string());
+            let name = seg.cell.binder.map_or("null".into(), |b| string(&binders[b].name.0));
+            let color = seg.cell.color
+                .map_or("null".into(), |[r, g, b]| format!("\"#{r:02x}{g:02x}{b:02x}\""));
+            writeln!(
+                w,
+                r#"{}{{"kind":"{}","from":[{},{}],"to":[{x2},{y2}],"node":{},"binder":{binder},"name":{name},"color":{color}}}"#,
+                sep(i),
+                kind(seg.cell.kind),
+                seg.x,
+                seg.y,
+                seg.cell.node,
+            )?;
+        }
+        writeln!(w, r#"],"nodes":["#)?;
+        for (i, node) in nodes.iter().enumerate() {
+            let Node { span, depth } = node;
+            writeln!(w, r#"{}{{"start":{},"end":{},"depth":{depth}}}"#, sep(i), span.start, span.end)?;
+        }
+        writeln!(w, r#"],"binders":["#)?;
+        for (i, binder) in binders.iter().enumerate() {
+            let Binder { name, node, depth } = binder;
+            writeln!(w, r#"{}{{"name":{},"node":{node},"depth":{depth}}}"#, sep(i), string(&name.0))?;
+        }
+        writeln!(w, r#"],"labels":["#)?;
+        for (i, (y, x, text)) in self.labels().enumerate() {
+            writeln!(w, r#"{}{{"x":{x},"y":{y},"text":{}}}"#, sep(i), string(text))?;
+        }
+        writeln!(w, "]}}")
+    }
+}
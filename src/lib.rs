@@ -1,10 +1,32 @@
 mod term;
 mod atom;
+mod parser;
+mod syntax;
+mod sugar;
+mod print;
+mod pretty;
+mod format;
+mod diag;
 mod graph;
 mod screen;
+mod color;
+mod svg;
+mod raster;
+mod json;
+mod glyphs;
 mod utils;
 
 pub use term::*;
 pub use atom::*;
+pub use parser::*;
+pub use syntax::Syntax;
+pub use sugar::Encoding;
+pub use print::*;
+pub use pretty::Doc;
+pub use diag::*;
 pub use graph::*;
 pub use screen::*;
+pub use color::*;
+pub use svg::*;
+pub use raster::*;
+pub use json::*;
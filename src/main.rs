@@ -1,21 +1,44 @@
-use std::{env::args, fmt::{self, Display}, io::{self, read_to_string, stdin, stdout}, process::exit};
+use std::{env::{self, args}, fmt::{self, Write as _}, fs::File, io::{self, read_to_string, stdin, stdout}, mem::take, path::Path, process::exit, str::FromStr};
 
 use getopts_macro::getopts_options;
-use lambda_graph::{expr, Error, GraphCtx, OutputCtx, Term};
-use line_column::line_column;
+use lambda_graph::{parse_color, Atom, Coloring, Diagnostic, Encoding, Error, GraphCtx, JsonCtx, OutputCtx, RasterCtx, PALETTE, RasterFormat, Screen, Sources, Style, SvgCtx, Syntax, Term};
 
 fn main() {
     let options = getopts_options! {
         -l, --lambda*       "output raw lambda";
+        -L, --lambda-refs*  "output raw lambda, keep definition names";
         -s, --simple*       "output simple paren lambda";
+        -m, --minimal*      "output minimal paren lambda, can be parsed again";
         -p, --pretty*       "output pretty indent lambda";
         -P, --pretty-n=n    "output pretty indent lambda, custom level";
+        -w, --width=n       "pretty output width [default: COLUMNS or 80]";
+        -F, --fmt*          "output formatted source, keep comments [default width: 80]";
         -n, --no-graph*     "no output graph";
-        -u, --unit=unit     "draw unit [default: 2 spaces]";
+        -u, --unit=unit     "draw unit [default: 2 spaces, `██` into output file]";
         -U, --unit-space=s  "draw space unit [default: 2 spaces]";
         -e, --func-extra=n  "after func extra units";
         -c, --call-extra=n  "after call extra units";
+            --style=name    "diagram style: standard, alternative, merged, arrows [default: standard]";
+        -b, --box*=name     "draw definition as a labelled box, with legend";
+            --trim-columns* "remove the columns only continuing horizontal lines";
+            --binder-names* "label abstractions by the binder names at the left";
+            --wire-names*   "label variable wires by the names at the bottom";
+        -f, --format=name   "graph output format: text, box, half, braille, svg, json, png, pbm, ppm [default: text, or by output extension]";
+        -o, --output=path   "write graph to the file, text without color, images take one input";
+            --cell=px       "svg cell size [default: 8]";
+            --stroke=px     "svg line width [default: 2]";
+            --scale=px      "raster pixels per cell, at least 1 [default: 4]";
+            --fg=color      "image line color [default: black]";
+            --bg=color      "image background color, `none` is transparent [default: white]";
+            --margin=px     "image margin [default: 8]";
+        -i, --idents*       "alphanumeric runs are single term";
+        -S, --syntax=name   "input syntax: default, haskell, lisp, debruijn [default: detect]";
+        -E, --encoding=name "list and string literal encoding: church, scott [default: church]";
             --no-color*     "no use color sequence";
+        -C, --coloring=mode "color cells by: binder, binder-depth, depth";
+            --palette=colors "comma separated colors of the coloring";
+            --truecolor*    "use 24-bit color sequence [default: by COLORTERM, else 256 colors]";
+            --deny-warnings* "exit with failure on warnings";
         -h, --help*         "show help message";
         -v, --version*      "show version";
     };
@@ -39,12 +62,22 @@ fn main() {
     }
 
     let lambda = matches.opt_present("lambda");
+    let lambda_refs = matches.opt_present("lambda-refs");
     let simple = matches.opt_present("simple");
+    let minimal = matches.opt_present("minimal");
     let pretty = matches.opt_present("pretty");
     let graph = !matches.opt_present("no-graph");
     let no_color = matches.opt_present("no-color");
-    let unit = matches.opt_str("unit").unwrap_or("  ".into());
+    let deny_warnings = matches.opt_present("deny-warnings");
+    let idents = matches.opt_present("idents");
+    let output = matches.opt_str("output");
+    let unit = matches.opt_str("unit")
+        .unwrap_or_else(|| if output.is_some() { "██" } else { "  " }.into());
     let space = matches.opt_str("unit-space");
+    let boxes = matches.opt_strs("box");
+    let trim_columns = matches.opt_present("trim-columns");
+    let binder_names = matches.opt_present("binder-names");
+    let wire_names = matches.opt_present("wire-names");
     let func_extra = matches.opt_get("func-extra")
         .unwrap_or_else(|e| {
             let arg = matches.opt_str("func-extra").unwrap();
@@ -57,12 +90,83 @@ fn main() {
             eprintln!("ArgError: on arg {arg:?} {e}");
             exit(2)
         });
+    let syntax = matches.opt_get::<Syntax>("syntax")
+        .unwrap_or_else(|e| {
+            let arg = matches.opt_str("syntax").unwrap();
+            eprintln!("ArgError: on arg {arg:?} {e}");
+            exit(2)
+        });
     let pretty_n = matches.opt_get("pretty-n")
         .unwrap_or_else(|e| {
             let arg = matches.opt_str("pretty-n").unwrap();
             eprintln!("ArgError: on arg {arg:?} {e}");
             exit(2)
         });
+    let width = matches.opt_get("width")
+        .unwrap_or_else(|e| {
+            let arg = matches.opt_str("width").unwrap();
+            eprintln!("ArgError: on arg {arg:?} {e}");
+            exit(2)
+        });
+    let fmt = matches.opt_present("fmt");
+    let by_ext = output.as_deref()
+        .and_then(|path| Path::new(path).extension()?.to_str()?.parse().ok())
+        .unwrap_or(Format::Text);
+    let format = matches.opt_get_default("format", by_ext)
+        .unwrap_or_else(|e| {
+            let arg = matches.opt_str("format").unwrap();
+            eprintln!("ArgError: on arg {arg:?} {e}");
+            exit(2)
+        });
+    let (cell, stroke) = (px(&matches, "cell", 8.0), px(&matches, "stroke", 2.0));
+    let (scale, margin) = (px(&matches, "scale", 4), px(&matches, "margin", 8));
+    if scale == 0 {
+        let arg = matches.opt_str("scale").unwrap();
+        eprintln!("ArgError: on arg {arg:?} scale must be at least 1");
+        exit(2)
+    }
+    let fg = matches.opt_str("fg").unwrap_or("black".into());
+    let bg = matches.opt_str("bg").unwrap_or("white".into());
+    let rgb = |color: &str| parse_color(color).unwrap_or_else(|| {
+        eprintln!("ArgError: on arg {color:?} unknown color");
+        exit(2)
+    });
+    let raster = RasterCtx {
+        scale,
+        fg: rgb(&fg),
+        bg: (bg != "none").then(|| rgb(&bg)),
+        margin,
+    };
+    let style = matches.opt_get_default("style", Style::default())
+        .unwrap_or_else(|e| {
+            let arg = matches.opt_str("style").unwrap();
+            eprintln!("ArgError: on arg {arg:?} {e}");
+            exit(2)
+        });
+    let coloring = matches.opt_get::<Coloring>("coloring")
+        .unwrap_or_else(|e| {
+            let arg = matches.opt_str("coloring").unwrap();
+            eprintln!("ArgError: on arg {arg:?} {e}");
+            exit(2)
+        });
+    let palette = matches.opt_str("palette")
+        .map_or(PALETTE.to_vec(), |colors| colors.split(',').map(|c| rgb(c.trim())).collect());
+    let truecolor = matches.opt_present("truecolor")
+        || env::var("COLORTERM").is_ok_and(|term| term == "truecolor" || term == "24bit");
+    let encoding = matches.opt_get_default("encoding", Encoding::default())
+        .unwrap_or_else(|e| {
+            let arg = matches.opt_str("encoding").unwrap();
+            eprintln!("ArgError: on arg {arg:?} {e}");
+            exit(2)
+        });
+
+    // one file for the graphs of all inputs, an image file holds one graph
+    let file = output.as_ref().map(|path| File::create(path).unwrap_or_else(|e| {
+        eprintln!("error: cannot create {path:?}: {e}");
+        exit(1)
+    }));
+    let single = file.is_some() && matches!(format, Format::Raster(_));
+    let mut inputs = 0;
 
     matches.free.is_empty()
         .then(|| {
@@ -78,76 +182,276 @@ fn main() {
         .chain(matches.free)
         .for_each(|s|
     {
-        let expr = match expr(&s) {
+        inputs += 1;
+        if single && inputs > 1 {
+            eprintln!("ArgError: image output file takes one input");
+            exit(2)
+        }
+        let mut sources = Sources::default();
+        sources.syntax = syntax;
+        sources.idents = idents;
+        sources.encoding = encoding;
+        if fmt {
+            match sources.format(None, &s, width.unwrap_or(80)) {
+                Ok(out) => print!("{out}"),
+                Err(e) => {
+                    eprintln!("{}\n", Diagnostic::from(&e));
+                    exit(3)
+                },
+            }
+            return;
+        }
+        let expr = match sources.parse_recover(None, &s, 10) {
             Ok(expr) => expr,
-            Err(e) => {
-                error(&s, e.location.offset, e);
+            Err(errors) => {
+                for e in &errors {
+                    eprintln!("{}\n", Diagnostic::from(e));
+                }
                 exit(3)
             },
         };
+        for warning in sources.warnings() {
+            eprintln!("{}\n", warning.diagnostic(&sources));
+        }
+        if deny_warnings && !sources.warnings().is_empty() {
+            eprintln!("error: warnings are denied");
+            exit(5)
+        }
         if lambda {
             println!("{expr}")
         }
+        if lambda_refs {
+            println!("{expr:#}")
+        }
         if simple {
             println!("{expr:o}")
         }
+        if minimal {
+            println!("{}", expr.minimal().idents(idents))
+        }
         if let Some(n) = pretty_n {
             println!("{expr:#.n$o}")
-        } else if pretty {
-            println!("{expr:#o}")
+        }
+        if pretty {
+            println!("{}", expr.minimal().idents(idents).pretty(width.unwrap_or_else(default_width)))
         }
         if !graph {
             return;
         }
 
-        let mut ctx = GraphCtx::default();
-        ctx.func_extra_unit = func_extra;
-        ctx.call_extra_unit = call_extra;
-
-        match ctx.foo(&expr) {
-            Ok(()) => {},
-            Err(Error::UndefinedTerm(Term(name, i))) => {
-                let (line, col) = line_column(&s, i);
-                #[allow(clippy::redundant_closure_call)]
-                (|e| error(&s, i, e))(format_args!(
-                    "error: undefined term `{name}` at {line}:{col}"
-                ));
+        let new_ctx = || {
+            let mut ctx = GraphCtx::default();
+            ctx.func_extra_unit = func_extra;
+            ctx.call_extra_unit = call_extra;
+            ctx.boxes = boxes.iter().map(|name| name.as_str().into()).collect();
+            ctx.wire_labels = wire_names;
+            ctx.style = style;
+            ctx
+        };
+        let finish = |ctx: &mut GraphCtx| {
+            if let Some(coloring) = coloring {
+                ctx.paint(coloring, &palette);
+            }
+            if trim_columns {
+                ctx.screen.trim_columns();
+            }
+            if binder_names {
+                ctx.label_binders();
+            }
+        };
+        let mut ctx = new_ctx();
+
+        if let Err(e) = ctx.foo(&expr) {
+            eprintln!("{}", render_error(e, &sources));
+            exit(4)
+        }
+        finish(&mut ctx);
+
+        // legend of boxed definitions, inputs as lambdas
+        let mut legends = vec![];
+        let mut boxed = take(&mut ctx.boxed);
+        let mut tables = (take(&mut ctx.nodes), take(&mut ctx.binders));
+        let mut i = 0;
+        while let Some((name, atom)) = boxed.get(i).cloned() {
+            let legend = atom.free_terms().into_iter()
+                .rfold(atom, |atom, term| {
+                    let span = atom.span();
+                    Atom::Func(term, atom, span).into()
+                });
+            let mut ctx = new_ctx();
+            ctx.boxed = boxed;
+            (ctx.nodes, ctx.binders) = tables;
+            if let Err(e) = ctx.foo(&legend) {
+                eprintln!("{}", render_error(e, &sources));
                 exit(4)
-            },
+            }
+            finish(&mut ctx);
+            legends.push((name, ctx.screen));
+            boxed = ctx.boxed;
+            tables = (ctx.nodes, ctx.binders);
+            i += 1;
         }
 
-        let octx = &mut OutputCtx {
-            writer: StdoutFmt(stdout().lock()),
-            has_color: !no_color,
-            space: space.as_deref(),
-            solid: &unit,
+        let writer: Box<dyn io::Write> = match &file {
+            Some(file) => Box::new(file),
+            None => Box::new(stdout().lock()),
+        };
+        let mut writer = IoFmt { writer, error: None };
+        let mut screen = ctx.screen;
+        if !matches!(format, Format::Text) {
+            for (name, legend) in &legends {
+                let mut head = Screen::default();
+                head.label(1, 0, format!("{name}:"));
+                screen.append(&head);
+                screen.append(legend);
+            }
+        }
+        let result = match format {
+            Format::Text => {
+                let octx = &mut OutputCtx {
+                    writer: &mut writer,
+                    has_color: !no_color && file.is_none(),
+                    truecolor,
+                    space: space.as_deref(),
+                    solid: &unit,
+                };
+                screen.print(octx).and_then(|()| {
+                    legends.iter().try_for_each(|(name, screen)| {
+                        writeln!(octx.writer, "\n{name}:")?;
+                        screen.print(octx)
+                    })
+                })
+            },
+            Format::Box => screen.print_box(&mut writer),
+            Format::Half => screen.print_half(&mut writer),
+            Format::Braille => screen.print_braille(&mut writer),
+            Format::Svg => {
+                screen.print_svg(&mut SvgCtx {
+                    writer: &mut writer,
+                    cell,
+                    stroke,
+                    fg: &fg,
+                    bg: Some(&*bg).filter(|&bg| bg != "none"),
+                    margin: margin as f64,
+                })
+            },
+            Format::Json => {
+                let (nodes, binders) = &tables;
+                screen.print_json(&mut JsonCtx { writer: &mut writer, nodes, binders })
+            },
+            Format::Raster(format) => {
+                let writer = &mut writer.writer;
+                let result = screen.print_raster(&raster, format, writer)
+                    .and_then(|()| writer.flush());
+                if let Err(e) = result {
+                    exit_on_io(e)
+                }
+                Ok(())
+            },
         };
-        ctx.screen.print(octx);
+        if result.is_err() {
+            exit_on_io(writer.error.unwrap_or_else(|| io::Error::other("formatter error")))
+        }
     });
 }
 
-fn error(s: &str, i: usize, e: impl Display) {
-    let near = s[i..]
-        .chars()
-        .take_while(char_classes::any!(^" \t\r\n"))
-        .take(5)
-        .collect::<String>();
-    if i >= s.len() {
-        eprint!("at eof ");
-    } else if !near.is_empty() {
-        eprint!("near `{near}` ");
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    Text,
+    /// Box-drawing chars
+    Box,
+    /// Two cells per char
+    Half,
+    /// Eight cells per char
+    Braille,
+    Svg,
+    /// Segments and tables of the layout
+    Json,
+    Raster(RasterFormat),
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "text" => Format::Text,
+            "box" => Format::Box,
+            "half" => Format::Half,
+            "braille" => Format::Braille,
+            "svg" => Format::Svg,
+            "json" => Format::Json,
+            "png" => Format::Raster(RasterFormat::Png),
+            "pbm" => Format::Raster(RasterFormat::Pbm),
+            "ppm" => Format::Raster(RasterFormat::Ppm),
+            _ => return Err(format!("unknown format `{s}`")),
+        })
     }
-    eprintln!("{e}");
 }
 
-struct StdoutFmt<'a>(io::StdoutLock<'a>);
-impl fmt::Write for StdoutFmt<'_> {
+/// Write into io, keep the io error of the failed write
+struct IoFmt<W> {
+    writer: W,
+    error: Option<io::Error>,
+}
+impl<W: io::Write> fmt::Write for IoFmt<W> {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        io::Write::write(&mut self.0, s.as_bytes()).unwrap();
-        Ok(())
+        self.writer.write_all(s.as_bytes()).map_err(|e| {
+            self.error = Some(e);
+            fmt::Error
+        })
+    }
+}
+
+/// Value of the numeric option, or the default
+fn px<T: FromStr>(matches: &getopts_macro::getopts::Matches, name: &str, default: T) -> T
+where T::Err: fmt::Display,
+{
+    matches.opt_get_default(name, default)
+        .unwrap_or_else(|e| {
+            let arg = matches.opt_str(name).unwrap();
+            eprintln!("ArgError: on arg {arg:?} {e}");
+            exit(2)
+        })
+}
+
+/// Exit quietly on closed pipe, else report the error
+fn exit_on_io(e: io::Error) -> ! {
+    if e.kind() == io::ErrorKind::BrokenPipe {
+        exit(0)
+    }
+    eprintln!("error: {e}");
+    exit(1)
+}
+
+/// Width from `COLUMNS`, or 80
+fn default_width() -> usize {
+    std::env::var("COLUMNS").ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(80)
+}
+
+/// Diagnostic of the undefined term, noted by the definitions using it
+fn render_error(mut e: Error, sources: &Sources) -> Diagnostic {
+    let mut uses = vec![];
+    while let Error::InDefinition(name, sub) = e {
+        uses.push(name);
+        e = *sub;
     }
-    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> fmt::Result {
-        io::Write::write_fmt(&mut self.0, args).unwrap();
-        Ok(())
+    let Error::UndefinedTerm(Term(name, span)) = e else { unreachable!() };
+    let (source, i) = sources.locate(span.start).unwrap();
+    let mut diag = Diagnostic::error(
+        format!("undefined term `{name}`"),
+        source,
+        (i, i + span.end-span.start),
+    );
+    for Term(name, span) in uses.iter().rev() {
+        let (source, i) = sources.locate(span.start).unwrap();
+        let (line, col) = source.line_column(i);
+        diag = diag.note(format!(
+            "in definition `{name}` used at {}:{line}:{col}",
+            source.name(),
+        ));
     }
+    diag
 }
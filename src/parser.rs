@@ -0,0 +1,833 @@
+use core::fmt;
+use std::{collections::{BTreeMap, BTreeSet, HashSet}, fs, io, mem::take, path::{Path, PathBuf}, rc::Rc};
+
+use char_classes::any;
+use line_column::line_column;
+use crate::{format::{Item, Program}, sugar, Atom, Diagnostic, Encoding, Span, Syntax, Term};
+
+/// Source text, positions of all sources are in the same offset space
+#[derive(Debug, Clone)]
+pub struct Source {
+    pub path: Option<Rc<Path>>,
+    pub base: usize,
+    pub text: Rc<str>,
+}
+
+impl Source {
+    /// Offset in this source to line and column
+    pub fn line_column(&self, offset: usize) -> (u32, u32) {
+        line_column(&self.text, offset.min(self.text.len()))
+    }
+
+    pub fn name(&self) -> String {
+        self.path.as_ref()
+            .map_or_else(|| "<input>".into(), |path| path.display().to_string())
+    }
+}
+
+/// All sources used by parsing, include imported files
+#[derive(Debug, Clone, Default)]
+pub struct Sources {
+    files: Vec<Source>,
+    warnings: Vec<Warning>,
+    /// Syntax of the parsing sources, or detect from source
+    pub syntax: Option<Syntax>,
+    /// Alphanumeric runs are single term, or enable by `#pragma idents` line
+    pub idents: bool,
+    /// Encoding of the list and string literals
+    pub encoding: Encoding,
+}
+
+fn has_pragma(s: &str, pragma: &str) -> bool {
+    s.lines().any(|line| line.trim_end() == format!("#pragma {pragma}"))
+}
+
+impl Sources {
+    fn next_base(&self) -> usize {
+        self.files.last()
+            .map_or(0, |last| last.base + last.text.len() + 1)
+    }
+
+    fn add(&mut self, path: Option<Rc<Path>>, text: &str) -> Source {
+        let base = self.next_base();
+        let source = Source { path, base, text: text.into() };
+        self.files.push(source.clone());
+        source
+    }
+
+    /// Warnings of all parsed sources
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    /// Find the source of global offset, and the offset in the source
+    pub fn locate(&self, offset: usize) -> Option<(&Source, usize)> {
+        self.files.iter()
+            .rfind(|source| source.base <= offset)
+            .map(|source| (source, offset - source.base))
+    }
+
+    /// Parse the source, imports relative to the directory of `path`,
+    /// or current directory
+    pub fn parse(&mut self, path: Option<&Path>, s: &str) -> Result<Atom, ParseError> {
+        let dir = path.and_then(Path::parent)
+            .map_or_else(|| ".".into(), Path::to_path_buf);
+        let source = self.add(path.map(Into::into), s);
+        let syntax = self.syntax.unwrap_or_else(|| Syntax::detect(s));
+        if syntax != Syntax::Default {
+            return crate::syntax::parse(syntax, s, source.base)
+                .map_err(|e| ParseError::Syntax(source, e));
+        }
+        let mut ctx = Ctx {
+            idents: self.idents || has_pragma(s, "idents"),
+            encoding: self.encoding,
+            sources: take(self),
+            importing: path.into_iter().map(Path::to_path_buf).collect(),
+            dir,
+            base: source.base,
+            ..Default::default()
+        };
+        let result = parser::expr(s, &mut ctx);
+        *self = ctx.sources;
+
+        match (result, ctx.error) {
+            (_, Some(e)) => Err(e),
+            (Ok(atom), None) => Ok(atom),
+            (Err(e), None) => Err(ParseError::Syntax(source, e)),
+        }
+    }
+}
+
+impl Sources {
+    /// Parse the top level statements and comments for formatting,
+    /// imports are not read
+    pub(crate) fn parse_program(
+        &mut self,
+        path: Option<&Path>,
+        s: &str,
+    ) -> Result<Program, ParseError> {
+        let source = self.add(path.map(Into::into), s);
+        let warnings = self.warnings.len();
+        let mut ctx = Ctx {
+            idents: self.idents || has_pragma(s, "idents"),
+            encoding: self.encoding,
+            sources: take(self),
+            base: source.base,
+            fmt: true,
+            ..Default::default()
+        };
+        let result = parser::program(s, &mut ctx);
+        *self = take(&mut ctx.sources);
+        self.warnings.truncate(warnings);
+
+        if let Some(e) = ctx.error {
+            return Err(e);
+        }
+        result.map_err(|e| ParseError::Syntax(source, e))?;
+        Ok(Program {
+            items: ctx.items.into_values().collect(),
+            comments: ctx.comments,
+            written: ctx.written,
+            idents: ctx.idents,
+        })
+    }
+}
+
+/// Byte range of the statement around `offset`, end at `;`
+fn statement_around(text: &str, offset: usize) -> Option<(usize, usize)> {
+    let mut ends = vec![];
+    let mut chars = text.char_indices();
+    while let Some((i, ch)) = chars.next() {
+        match ch {
+            '#' => { chars.by_ref().find(|&(_, ch)| ch == '\n'); },
+            '"' | '`' => { chars.by_ref().find(|&(_, end)| end == ch || end == '\n'); },
+            ';' => ends.push(i),
+            _ => (),
+        }
+    }
+    let start = ends.iter().rev()
+        .find(|&&i| i < offset)
+        .map_or(0, |i| i+1);
+    let end = ends.iter().find(|&&i| i >= offset)?;
+    Some((start, end+1))
+}
+
+impl Sources {
+    /// Parse like [`Self::parse`], but skip the statement of syntax error and continue,
+    /// report at most `limit` errors
+    pub fn parse_recover(
+        &mut self,
+        path: Option<&Path>,
+        s: &str,
+        limit: usize,
+    ) -> Result<Atom, Vec<ParseError>> {
+        let init = self.clone();
+        let base = init.next_base();
+        let mut text = s.to_owned();
+        let mut errors = vec![];
+        let mut blanked = vec![];
+
+        loop {
+            *self = init.clone();
+            let e = match self.parse(path, &text) {
+                Ok(atom) if errors.is_empty() => return Ok(atom),
+                Ok(_) => break,
+                Err(e) => e,
+            };
+            let local = e.source().base == base;
+            // caused by a skipped statement, only blanks up to the error
+            let offset = e.offset();
+            let cascaded = local && blanked.iter().any(|&(start, end)| {
+                start <= offset && (offset < end || text[end..offset].trim().is_empty())
+            });
+            if cascaded {
+                break;
+            }
+            let skip = local.then(|| statement_around(&text, e.offset())).flatten();
+            errors.push(e);
+
+            let Some((start, end)) = skip.filter(|_| errors.len() < limit) else { break };
+            let blank = text[start..end].chars()
+                .map(|ch| if ch == '\n' { "\n".into() } else { " ".repeat(ch.len_utf8()) })
+                .collect::<String>();
+            text.replace_range(start..end, &blank);
+            blanked.push((start, end));
+        }
+
+        // point to original text
+        for e in &mut errors {
+            let source = match e {
+                ParseError::Syntax(source, _)
+                | ParseError::Import(source, ..)
+                | ParseError::ImportCycle(source, ..)
+                | ParseError::Case(source, ..) => source,
+            };
+            if source.base == base {
+                source.text = s.into();
+            }
+        }
+        Err(errors)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// Define a name already defined, and the previous definition
+    Redefine(Term, Term),
+    /// Definition never used
+    Unused(Term),
+    /// Binder named as a definition, and the definition
+    Shadow(Term, Term),
+}
+
+impl Warning {
+    pub fn span(&self) -> Span {
+        match self {
+            Warning::Redefine(term, _)
+            | Warning::Unused(term)
+            | Warning::Shadow(term, _) => term.1,
+        }
+    }
+
+    pub fn diagnostic(&self, sources: &Sources) -> Diagnostic {
+        let span = self.span();
+        let (source, start) = sources.locate(span.start).unwrap();
+        let at = |term: &Term| {
+            let (source, i) = sources.locate(term.1.start).unwrap();
+            let (line, col) = source.line_column(i);
+            format!("{}:{line}:{col}", source.name())
+        };
+        let (message, note) = match self {
+            Warning::Redefine(name, prev) => (
+                format!("redefine `{name}`"),
+                Some(format!("previous definition at {}", at(prev))),
+            ),
+            Warning::Unused(name) => (format!("unused definition `{name}`"), None),
+            Warning::Shadow(name, def) => (
+                format!("binder `{name}` is named as a definition"),
+                Some(format!("definition at {}", at(def))),
+            ),
+        };
+        let mut diag = Diagnostic::error(message, source, (start, start + span.end-span.start));
+        diag.level = "warning";
+        diag.notes.extend(note);
+        diag
+    }
+}
+
+/// Any [`Atom::Ref`] to `body` in the atom, include in the bodies of other definitions
+fn uses(atom: &Atom, body: &Rc<Atom>, seen: &mut HashSet<*const Atom>) -> bool {
+    match atom {
+        Atom::Term(_) => false,
+        Atom::Ref(_, sub) if Rc::ptr_eq(sub, body) => true,
+        Atom::Ref(_, sub) => seen.insert(Rc::as_ptr(sub)) && uses(sub, body, seen),
+        Atom::Call(fun, a, _) => uses(fun, body, seen) || uses(a, body, seen),
+        Atom::Func(_, sub, _) => uses(sub, body, seen),
+    }
+}
+
+#[derive(Debug)]
+pub enum ParseError {
+    Syntax(Source, peg::error::ParseError<peg::str::LineCol>),
+    /// Failed to read imported file
+    Import(Source, usize, PathBuf, io::Error),
+    /// Import a file that is being imported
+    ImportCycle(Source, usize, PathBuf),
+    /// Invalid arm of a `case` expression, local span and the message
+    Case(Source, Span, String),
+}
+
+impl ParseError {
+    /// The source of the error located
+    pub fn source(&self) -> &Source {
+        match self {
+            ParseError::Syntax(source, _)
+            | ParseError::Import(source, ..)
+            | ParseError::ImportCycle(source, ..)
+            | ParseError::Case(source, ..) => source,
+        }
+    }
+
+    /// Offset of the error in [`Self::source`]
+    pub fn offset(&self) -> usize {
+        match self {
+            ParseError::Syntax(_, e) => e.location.offset,
+            ParseError::Import(_, at, ..)
+            | ParseError::ImportCycle(_, at, _) => *at,
+            ParseError::Case(_, span, _) => span.start,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let source = self.source();
+        let (line, col) = source.line_column(self.offset());
+        write!(f, "{}:{line}:{col}: ", source.name())?;
+        match self {
+            ParseError::Syntax(_, e) => write!(f, "expected {}", e.expected),
+            ParseError::Import(_, _, path, e) => {
+                write!(f, "cannot import {:?}: {e}", path.display())
+            },
+            ParseError::ImportCycle(_, _, path) => {
+                write!(f, "import cycle on {:?}", path.display())
+            },
+            ParseError::Case(_, _, message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError { }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Assoc {
+    Left,
+    Right,
+    None,
+}
+
+/// Associativity and precedence of an infix operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Fixity {
+    pub assoc: Assoc,
+    pub prec: u8,
+}
+
+/// Use of infix operator, the definition and fixity
+type Op = (Term, Rc<Atom>, Fixity);
+
+/// Resolve `a op b op c` by the fixities of operators
+fn infix(first: Atom, rest: Vec<(Op, Atom)>) -> Result<Atom, &'static str> {
+    fn reduce(operands: &mut Vec<Atom>, (op, body, _): Op) {
+        let b = operands.pop().unwrap();
+        let a = operands.pop().unwrap();
+        let span = a.span().to(b.span());
+        let fun = Atom::Call(Atom::Ref(op.clone(), body).into(), a.into(), span);
+        operands.push(Atom::Call(fun.into(), b.into(), span));
+    }
+    let mut operands = vec![first];
+    let mut ops = Vec::<Op>::new();
+    for (op, operand) in rest {
+        let fixity = op.2;
+        while let Some(&(_, _, top)) = ops.last() {
+            if top.prec == fixity.prec && (top.assoc != fixity.assoc || top.assoc == Assoc::None) {
+                return Err("parentheses for operators of same precedence");
+            }
+            if top.prec < fixity.prec || top.prec == fixity.prec && top.assoc == Assoc::Right {
+                break;
+            }
+            reduce(&mut operands, ops.pop().unwrap());
+        }
+        ops.push(op);
+        operands.push(operand);
+    }
+    while let Some(op) = ops.pop() {
+        reduce(&mut operands, op);
+    }
+    Ok(operands.pop().unwrap())
+}
+
+/// Constructor of a data declaration
+#[derive(Debug)]
+struct Ctor {
+    /// Constructors of the data
+    data: Rc<[Term]>,
+    index: usize,
+    arity: usize,
+}
+
+#[derive(Debug, Default)]
+struct Ctx {
+    /// Definitions in scope, with the defining name
+    defines: BTreeMap<Term, Vec<(Term, Rc<Atom>)>>,
+    sources: Sources,
+    /// Directory of the parsing file
+    dir: PathBuf,
+    importing: Vec<PathBuf>,
+    /// Base offset of the parsing source
+    base: usize,
+    idents: bool,
+    encoding: Encoding,
+    /// Constructors by the defined body
+    ctors: Vec<(Rc<Atom>, Ctor)>,
+    /// Infix operators by the defined body
+    fixities: Vec<(Rc<Atom>, Fixity)>,
+    error: Option<ParseError>,
+    /// Keep the statements and comments for formatting, skip reading imports
+    fmt: bool,
+    items: BTreeMap<usize, (Span, Item)>,
+    comments: BTreeMap<usize, Rc<str>>,
+    /// Local starts of the literals and `case`, not rebuilt from the atoms
+    written: BTreeSet<usize>,
+}
+
+impl Ctx {
+    fn warn(&mut self, warning: Warning) {
+        let warnings = &mut self.sources.warnings;
+        if !warnings.iter().any(|w| *w == warning && w.span().start == warning.span().start) {
+            warnings.push(warning);
+        }
+    }
+
+    fn lookup(&self, name: &Term) -> Option<&(Term, Rc<Atom>)> {
+        self.defines.get(name).and_then(|defs| defs.last())
+    }
+
+    fn define(&mut self, name: Term, e: Rc<Atom>) {
+        if let Some((prev, _)) = self.lookup(&name) {
+            self.warn(Warning::Redefine(name.clone(), prev.clone()));
+        }
+        self.defines.entry(name.clone()).or_default().push((name, e));
+    }
+
+    /// End the scope of definition, `rest` is the atom in the scope
+    fn undefine(&mut self, name: &Term, rest: Option<&Atom>) {
+        let (name, body) = self.defines.get_mut(name).unwrap().pop().unwrap();
+        if rest.is_some_and(|rest| !uses(rest, &body, &mut HashSet::new())) {
+            self.warn(Warning::Unused(name));
+        }
+    }
+
+    /// Warn the binder named as a definition, the definition is still used in the body
+    fn bind(&mut self, name: &Term) {
+        if let Some((def, _)) = self.lookup(name) {
+            self.warn(Warning::Shadow(name.clone(), def.clone()));
+        }
+    }
+
+    fn record(&mut self, span: Span, item: impl FnOnce() -> Item) {
+        if self.fmt {
+            self.items.insert(span.start, (span, item()));
+        }
+    }
+
+    fn comment(&mut self, at: usize, s: &str) {
+        if self.fmt {
+            self.comments.insert(at, s.into());
+        }
+    }
+
+    fn sugar(&mut self, at: usize) {
+        if self.fmt {
+            self.written.insert(at - self.base);
+        }
+    }
+
+    /// Definition of the name is an infix operator
+    fn fixity(&self, name: &Term) -> Option<Op> {
+        let (_, body) = self.lookup(name)?;
+        let (_, fixity) = self.fixities.iter().find(|(op, _)| Rc::ptr_eq(op, body))?;
+        Some((name.clone(), body.clone(), *fixity))
+    }
+
+    /// Define constructors of the data
+    fn data(&mut self, ctors: &[(Term, Vec<Term>)], span: Span) -> Vec<Rc<Atom>> {
+        let data = ctors.iter().map(|(name, _)| name.clone()).collect::<Rc<[_]>>();
+        ctors.iter().enumerate().map(|(index, (name, fields))| {
+            let body = Rc::new(sugar::constructor(fields, index, ctors.len(), span));
+            let ctor = Ctor { data: data.clone(), index, arity: fields.len() };
+            self.ctors.push((body.clone(), ctor));
+            self.define(name.clone(), body.clone());
+            body
+        }).collect()
+    }
+
+    /// Apply the scrutinee to arms in order of the constructors,
+    /// an invalid arm is reported by [`ParseError::Case`]
+    fn case(&mut self, e: Atom, arms: Vec<(Term, usize, Atom)>, span: Span) -> Result<Atom, &'static str> {
+        match self.case_arms(arms, span) {
+            Ok(ordered) => {
+                Ok(ordered.into_iter().fold(e, |e, arm| Atom::Call(e.into(), arm.into(), span)))
+            },
+            Err((span, message)) => {
+                let span = Span::new(span.start - self.base, span.end - self.base);
+                let e = ParseError::Case(self.current(), span, message);
+                self.error.get_or_insert(e);
+                Err("case")
+            },
+        }
+    }
+
+    fn case_arms(
+        &self,
+        arms: Vec<(Term, usize, Atom)>,
+        span: Span,
+    ) -> Result<Vec<Atom>, (Span, String)> {
+        let mut ordered = vec![];
+        let mut data = None::<&Rc<[Term]>>;
+        for (name, arity, arm) in arms {
+            let arm_span = name.1.to(arm.span());
+            let ctor = self.lookup(&name)
+                .and_then(|(_, body)| self.ctors.iter().find(|(ctor, _)| Rc::ptr_eq(ctor, body)))
+                .map(|(_, ctor)| ctor)
+                .ok_or_else(|| (arm_span, format!("`{name}` is not a data constructor")))?;
+            if ctor.arity != arity {
+                let plural = if ctor.arity == 1 { "" } else { "s" };
+                let message = format!("`{name}` has {} field{plural}, the arm binds {arity}", ctor.arity);
+                return Err((arm_span, message));
+            }
+            if data.is_some_and(|data| !Rc::ptr_eq(data, &ctor.data)) {
+                return Err((arm_span, format!("`{name}` is a constructor of another data")));
+            }
+            if ordered.iter().any(|&(index, _)| index == ctor.index) {
+                return Err((arm_span, format!("duplicate arm of `{name}`")));
+            }
+            data = Some(&ctor.data);
+            ordered.push((ctor.index, arm));
+        }
+        ordered.sort_by_key(|&(index, _)| index);
+        let data = data.map_or(&[][..], |data| &data[..]);
+        if let Some(missing) = data.iter().enumerate()
+            .find(|&(i, _)| ordered.get(i).is_none_or(|&(index, _)| index != i))
+        {
+            return Err((span, format!("missing arm of `{}`", missing.1)));
+        }
+        Ok(ordered.into_iter().map(|(_, arm)| arm).collect())
+    }
+
+    fn current(&self) -> Source {
+        self.sources.locate(self.base).unwrap().0.clone()
+    }
+
+    /// Parse definitions of the file into current scope, returns defined names
+    fn import(&mut self, path: &str, at: usize) -> Result<Vec<(Term, bool)>, &'static str> {
+        if self.error.is_some() {
+            return Err("import");
+        }
+        if self.fmt {
+            return Ok(vec![]);
+        }
+        let path = self.dir.join(path);
+        let full = fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+
+        if self.importing.contains(&full) {
+            self.error = ParseError::ImportCycle(self.current(), at, path).into();
+            return Err("import");
+        }
+        let text = match fs::read_to_string(&full) {
+            Ok(text) => text,
+            Err(e) => {
+                self.error = ParseError::Import(self.current(), at, path, e).into();
+                return Err("import");
+            },
+        };
+
+        let source = self.sources.add(Some(path.as_path().into()), &text);
+        let dir = full.parent().map_or_else(|| ".".into(), Path::to_path_buf);
+        let dir = std::mem::replace(&mut self.dir, dir);
+        let base = std::mem::replace(&mut self.base, source.base);
+        let idents = self.idents || has_pragma(&text, "idents");
+        let idents = std::mem::replace(&mut self.idents, idents);
+        self.importing.push(full);
+
+        let result = parser::defines(&text, self);
+
+        self.importing.pop();
+        self.dir = dir;
+        self.base = base;
+        self.idents = idents;
+
+        match result {
+            Ok(names) if self.error.is_none() => {
+                Ok(names.into_iter().map(|name| (name, false)).collect())
+            },
+            Ok(_) => Err("import"),
+            Err(e) => {
+                self.error.get_or_insert(ParseError::Syntax(source, e));
+                Err("import")
+            },
+        }
+    }
+}
+
+/// Find a term name not in `avoid`
+pub(crate) fn fresh_term(base: &str, avoid: &[Term], span: Span) -> Term {
+    let name = (0..)
+        .map(|i| if i == 0 { base.to_owned() } else { format!("{base}{i}") })
+        .find(|name| avoid.iter().all(|t| **t != **name))
+        .unwrap();
+    Term(name.into(), span)
+}
+
+/// Turn the uses of definitions `names` back into plain terms,
+/// bodies of other definitions are untouched
+fn unresolve(atom: &Atom, names: &[Term]) -> Atom {
+    match atom {
+        Atom::Ref(name, _) if names.contains(name) => name.clone().into(),
+        Atom::Term(_) | Atom::Ref(..) => atom.clone(),
+        Atom::Call(fun, a, span) => Atom::Call(
+            unresolve(fun, names).into(),
+            unresolve(a, names).into(),
+            *span,
+        ),
+        Atom::Func(p, e, span) => {
+            Atom::Func(p.clone(), unresolve(e, names).into(), *span)
+        },
+    }
+}
+
+/// Tie the knot of a recursive definition group through a fixed-point combinator
+///
+/// - `F := Y (λF. e)`
+/// - `Fi := T seli`, `T := Y (λp. (λF1..Fn. λs. s e1..en) (p sel1)..(p seln))`
+fn recursive(defs: Vec<(Term, Atom)>, span: Span) -> Vec<(Term, Rc<Atom>)> {
+    let term = |name: &str| Term(name.into(), span);
+    let var = |name: &str| Rc::new(Atom::Term(term(name)));
+    let func = |name: Term, e: Rc<Atom>| Rc::new(Atom::Func(name, e, span));
+    let call = |a: Rc<Atom>, b: Rc<Atom>| Rc::new(Atom::Call(a, b, span));
+
+    let half = func(term("x"), call(var("f"), call(var("x"), var("x"))));
+    let y = func(term("f"), call(half.clone(), half));
+
+    let names = defs.iter().map(|(name, _)| name.clone()).collect::<Vec<_>>();
+    let bodies = defs.iter()
+        .map(|(_, e)| Rc::new(unresolve(e, &names)))
+        .collect::<Vec<_>>();
+
+    if let [name] = &names[..] {
+        let body = func(name.clone(), bodies[0].clone());
+        return vec![(name.clone(), call(y, body))];
+    }
+
+    let avoid = bodies.iter()
+        .flat_map(|e| e.free_terms())
+        .chain(names.iter().cloned())
+        .collect::<Vec<_>>();
+    let (pt, st) = (fresh_term("p", &avoid, span), fresh_term("s", &avoid, span));
+    let n = names.len();
+    let select = |i: usize| (0..n).rfold(
+        var(&format!("x{i}")),
+        |e, j| func(term(&format!("x{j}")), e),
+    );
+
+    let tuple = bodies.iter().cloned()
+        .fold(Rc::new(Atom::Term(st.clone())), call);
+    let group = names.iter().cloned().rfold(func(st, tuple), |e, name| func(name, e));
+    let applied = (0..n).fold(group, |e, i| {
+        call(e, call(Rc::new(Atom::Term(pt.clone())), select(i)))
+    });
+    let knot = call(y, func(pt, applied));
+
+    names.into_iter().enumerate()
+        .map(|(i, name)| (name, call(knot.clone(), select(i))))
+        .collect()
+}
+
+peg::parser!(grammar parser(ctx: &mut Ctx) for str {
+    rule _() = quiet!{[ch if any!(" \t\r\n", ch)]*} comment()?
+    rule comment() = quiet!{p:position!() s:$("#" [^'\r' | '\n']*) ({ ctx.comment(p, s) }) _}
+    rule pos() -> usize = p:position!() { ctx.base + p }
+    rule ident_char() = [ch if ch.is_alphanumeric() || any!("_$'", ch)]
+    rule idents() = {? if ctx.idents { Ok(()) } else { Err("identifier mode") } }
+    rule term() -> Term
+        = p:pos()
+          t:quiet!{
+            idents() s:$([ch if ch != 'λ' && (ch.is_alphabetic() || any!("_$", ch))] ident_char()*)
+            { s.into() }
+            / ch:[ch if any!("a-zA-Z$_Σ-κμ-ϧ+*/%!-", ch)]
+            { ch.encode_utf8(&mut [0, 0]).into() }
+            / "`" s:$(([^ch if any!(" \t\r\n`", ch)])+) "`"
+            { s.into() }
+          }
+          e:pos()
+        { Term(t, Span::new(p, e)) }
+        / expected!("term")
+    rule lambda() = "λ" / "^"
+    rule string() -> &'input str
+        = "\"" s:$([^'"' | '\r' | '\n']*) "\"" { s }
+    rule text() -> String
+        = "\"" s:(
+            "\\" ch:['"' | '\\' | 'n' | 't']
+            { match ch { 'n' => '\n', 't' => '\t', ch => ch } }
+            / [^'"' | '\\' | '\r' | '\n']
+          )* "\""
+        { s.into_iter().collect() }
+
+    rule define() -> (Term, Atom)
+        = name:term() _ ":=" _ e:expr_lambda() { (name, e) }
+
+    /// Definitions into current scope, returns defined names,
+    /// and should they be checked for use
+    rule define_stmt() -> Vec<(Term, bool)>
+        = s:position!() p:pos() "rec" !ident_char() e:pos() _
+          defs:define()++(_ "," _) _ ";" q:position!() _
+        {
+            // members used by the other members of the group
+            let names = defs.iter().map(|(name, _)| name.clone()).collect::<Vec<_>>();
+            let used = defs.iter().enumerate()
+                .flat_map(|(i, (_, e))| {
+                    let others = names.iter().enumerate().filter(move |&(j, _)| j != i);
+                    let free = unresolve(e, &names).free_terms();
+                    others.filter(move |(_, name)| free.contains(name)).map(|(j, _)| j)
+                })
+                .collect::<HashSet<_>>();
+            ctx.record(Span::new(s, q), || Item::Rec(defs.clone()));
+            let knots = recursive(defs, Span::new(p, e));
+            knots.into_iter().enumerate().map(|(i, (name, e))| {
+                ctx.define(name.clone(), e);
+                (name, !used.contains(&i))
+            }).collect()
+        }
+        / s:position!() def:define() _ ";" q:position!() _
+        {
+            let body = Rc::new(def.1);
+            ctx.record(Span::new(s, q), || Item::Define(def.0.clone(), body.clone()));
+            ctx.define(def.0.clone(), body);
+            vec![(def.0, true)]
+        }
+        / s:position!() assoc:assoc() _ prec:$(['0'..='9']+) _ def:define() _ ";" q:position!() _
+        {?
+            let fixity = Fixity { assoc, prec: prec.parse().or(Err("precedence"))? };
+            let body = Rc::new(def.1);
+            ctx.fixities.push((body.clone(), fixity));
+            ctx.record(Span::new(s, q), || Item::Infix(fixity, def.0.clone(), body.clone()));
+            ctx.define(def.0.clone(), body);
+            Ok(vec![(def.0, true)])
+        }
+        / s:position!() p:pos() "data" !ident_char() _ name:term() _ "=" _
+          ctors:(c:term() _ fields:(t:term() _ {t})* {(c, fields)})++("|" _)
+          ";" e:pos() q:position!() _
+        {
+            let bodies = ctx.data(&ctors, Span::new(p, e));
+            let names = ctors.iter().map(|(name, _)| (name.clone(), false)).collect();
+            ctx.record(Span::new(s, q), || Item::Data(name, ctors, bodies));
+            names
+        }
+        / p:position!() "import" !ident_char() _ path:string() _ ";" q:position!() _
+          names:({? ctx.import(path, p) })
+        {
+            ctx.record(Span::new(p, q), || Item::Import(path.into()));
+            names
+        }
+
+    rule assoc() -> Assoc
+        = "infixl" !ident_char() { Assoc::Left }
+        / "infixr" !ident_char() { Assoc::Right }
+        / "infix" !ident_char() { Assoc::None }
+    rule infix() -> Op
+        = t:term() {? ctx.fixity(&t).ok_or("infix operator") }
+
+    rule expr_definite() -> Atom
+        = names:define_stmt() e:expr_definite()
+        {
+            for (name, check) in names.iter().rev() {
+                ctx.undefine(name, check.then_some(&e));
+            }
+            e
+        }
+        / expr_lambda()
+    rule expr_lambda() -> Atom
+        = p:pos() (lambda() _)? names:(!infix() t:term() {t})++_ _ "." _ sub:expr_lambda()
+        {
+            let end = sub.span().end;
+            let first = names[0].1.start;
+            names.into_iter().rfold(sub, |sub, name| {
+                let start = if name.1.start == first { p } else { name.1.start };
+                ctx.bind(&name);
+                Atom::Func(name, sub.into(), Span::new(start, end))
+            })
+        }
+        / expr_call()
+    rule expr_call() -> Atom
+        = first:expr_apply() rest:(_ op:infix() _ e:expr_apply() { (op, e) })*
+        {? infix(first, rest) }
+    rule expr_apply() -> Atom
+        = atoms:(!("of" !ident_char() _ "{") !infix() a:expr_atom() {a})++_
+        { atoms.into_iter().reduce(|a, b| {
+            let span = a.span().to(b.span());
+            Atom::Call(a.into(), b.into(), span)
+        }).unwrap() }
+    rule expr_atom() -> Atom
+        = p:pos() "case" !ident_char() _ e:expr_definite() _ "of" !ident_char() _ "{" _
+          arms:case_arm()++(_ ";" _) _ (";" _)? "}" q:pos()
+        {? ctx.sugar(p); ctx.case(e, arms, Span::new(p, q)) }
+        / "(" _ op:infix() _ ")" { Atom::Ref(op.0, op.1) }
+        / t:term()
+        { match ctx.lookup(&t) {
+            Some((_, e)) => Atom::Ref(t, e.clone()),
+            None => t.into(),
+        } }
+        / p:pos() "(" _ e:expr_definite() _  ")" q:pos()
+        { match e {
+            Atom::Call(a, b, _) => Atom::Call(a, b, Span::new(p, q)),
+            Atom::Func(name, sub, _) => Atom::Func(name, sub, Span::new(p, q)),
+            e => e,
+        } }
+        / p:pos() "[" _ items:expr_definite()**(_ "," _) _ "]" q:pos()
+        { ctx.sugar(p); ctx.encoding.list(items, Span::new(p, q)) }
+        / p:pos() "<" _ items:expr_definite()**(_ "," _) _ ">" q:pos()
+        { ctx.sugar(p); sugar::tuple(items, Span::new(p, q)) }
+        / p:pos() s:text() q:pos()
+        { ctx.sugar(p); ctx.encoding.string(s.as_bytes(), Span::new(p, q)) }
+    /// Constructor name, number of fields, and the arm function
+    rule case_arm() -> (Term, usize, Atom)
+        = name:term() _ fields:(!"->" t:term() _ {t})* "->" _ body:expr_definite()
+        {
+            let arity = fields.len();
+            let end = body.span().end;
+            let arm = fields.into_iter().rfold(body, |body, field| {
+                let start = field.1.start;
+                ctx.bind(&field);
+                Atom::Func(field, body.into(), Span::new(start, end))
+            });
+            (name, arity, arm)
+        }
+    pub rule expr() -> Atom
+        = _ e:expr_definite() _ { e }
+    /// Top level statements for formatting
+    pub rule program()
+        = _ define_stmt()* (p:position!() e:expr_lambda() q:position!() _
+          { ctx.record(Span::new(p, q), || Item::Expr(e)) })?
+    pub rule defines() -> Vec<Term>
+        = _ names:define_stmt()*
+        { names.into_iter().flatten().map(|(name, _)| name).collect() }
+});
+
+pub type PegResult<T> = Result<T, peg::error::ParseError<peg::str::LineCol>>;
+
+/// Parse the default syntax, imports relative to current directory,
+/// like [`Sources::parse`] without the warnings
+pub fn expr(s: &str) -> Result<Atom, ParseError> {
+    let mut sources = Sources { syntax: Some(Syntax::Default), ..Default::default() };
+    sources.parse(None, s)
+}
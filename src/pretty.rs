@@ -0,0 +1,195 @@
+use std::rc::Rc;
+
+use unicode_width::UnicodeWidthStr;
+
+use crate::{parser::{Assoc, Fixity}, Atom, Minimal};
+
+/// Document of Wadler style layout
+#[derive(Debug, Clone)]
+pub enum Doc {
+    Nil,
+    Text(Rc<str>),
+    /// Newline, or the text when the group flattened
+    Line(&'static str),
+    Nest(usize, Rc<Doc>),
+    Concat(Rc<Doc>, Rc<Doc>),
+    /// Flatten all lines if it fits the width
+    Group(Rc<Doc>),
+}
+
+impl Doc {
+    pub fn text(s: impl Into<Rc<str>>) -> Self {
+        Doc::Text(s.into())
+    }
+
+    #[must_use]
+    pub fn nest(self, indent: usize) -> Self {
+        Doc::Nest(indent, self.into())
+    }
+
+    #[must_use]
+    pub fn group(self) -> Self {
+        Doc::Group(self.into())
+    }
+
+    #[must_use]
+    pub fn cat(self, other: Doc) -> Self {
+        Doc::Concat(self.into(), other.into())
+    }
+
+    /// Layout into the column width
+    pub fn render(&self, width: usize) -> String {
+        let mut out = String::new();
+        let mut col = 0;
+        let mut stack = vec![(0, false, self)];
+
+        while let Some((indent, flat, doc)) = stack.pop() {
+            match doc {
+                Doc::Nil => (),
+                Doc::Text(s) => {
+                    out.push_str(s);
+                    col += s.width();
+                },
+                Doc::Line(s) if flat => {
+                    out.push_str(s);
+                    col += s.width();
+                },
+                Doc::Line(_) => {
+                    out.truncate(out.trim_end_matches(' ').len());
+                    out.push('\n');
+                    out.extend((0..indent).map(|_| ' '));
+                    col = indent;
+                },
+                Doc::Nest(n, doc) => stack.push((indent+n, flat, doc)),
+                Doc::Concat(a, b) => {
+                    stack.push((indent, flat, b));
+                    stack.push((indent, flat, a));
+                },
+                Doc::Group(doc) => {
+                    let rest = stack.iter().rev().map(|&(_, flat, doc)| (flat, doc));
+                    let remain = width.saturating_sub(col);
+                    let fits = flat || fits(
+                        remain.try_into().unwrap_or(isize::MAX),
+                        [(true, &**doc)].into_iter().chain(rest),
+                    );
+                    stack.push((indent, fits, doc));
+                },
+            }
+        }
+        out
+    }
+}
+
+/// Text until the next newline fits the remaining width
+fn fits<'a>(mut remain: isize, docs: impl IntoIterator<Item = (bool, &'a Doc)>) -> bool {
+    let mut stack = docs.into_iter().collect::<Vec<_>>();
+    stack.reverse();
+    while let Some((flat, doc)) = stack.pop() {
+        if remain < 0 {
+            return false;
+        }
+        match doc {
+            Doc::Nil => (),
+            Doc::Text(s) => remain -= s.width() as isize,
+            Doc::Line(s) if flat => remain -= s.width() as isize,
+            Doc::Line(_) => return true,
+            Doc::Nest(_, doc) => stack.push((flat, doc)),
+            Doc::Concat(a, b) => {
+                stack.push((flat, b));
+                stack.push((flat, a));
+            },
+            Doc::Group(doc) => stack.push((flat, doc)),
+        }
+    }
+    remain >= 0
+}
+
+impl<'a> Minimal<'a> {
+    /// Document for the width aware layout
+    pub fn doc(&self) -> Doc {
+        self.doc_atom(self.atom(), false, false)
+    }
+
+    /// Layout into the column width
+    pub fn pretty(&self, width: usize) -> String {
+        self.doc().render(width)
+    }
+
+    /// Operator and operands of an infix operator call
+    fn infix(&self, atom: &'a Atom) -> Option<(&'a Atom, Fixity, &'a Atom, &'a Atom)> {
+        let Atom::Call(fun, b, _) = self.unref(atom) else { return None };
+        let Atom::Call(op, a, _) = self.unref(fun) else { return None };
+        Some((&**op, self.fixity(op)?, &**a, &**b))
+    }
+
+    /// Operand of the infix operator, `left` side
+    fn doc_operand(&self, atom: &'a Atom, outer: Fixity, left: bool) -> Doc {
+        let Some((_, fixity, ..)) = self.infix(atom) else {
+            return self.doc_atom(atom, false, true);
+        };
+        let assoc = if left { Assoc::Left } else { Assoc::Right };
+        let bare = fixity.prec > outer.prec
+            || fixity.prec == outer.prec && fixity.assoc == assoc && outer.assoc == assoc;
+        let doc = self.doc_atom(atom, false, false);
+        if bare {
+            doc
+        } else {
+            Doc::text("(").cat(doc.nest(1)).cat(Doc::text(")"))
+        }
+    }
+
+    fn doc_atom(&self, atom: &'a Atom, paren_call: bool, paren_func: bool) -> Doc {
+        let paren = |doc: Doc| Doc::text("(").cat(doc.nest(1)).cat(Doc::text(")"));
+        let infix = self.infix(atom);
+        match self.unref(atom) {
+            Atom::Ref(term, _) if self.fixity(atom).is_some() => {
+                Doc::text(format!("({})", self.term(term)))
+            },
+            Atom::Term(term) | Atom::Ref(term, _) => Doc::text(self.term(term)),
+            Atom::Call(..) | Atom::Func(..) if paren_call => {
+                paren(self.doc_atom(atom, false, false))
+            },
+            Atom::Func(..) if paren_func => paren(self.doc_atom(atom, false, false)),
+            Atom::Call(..) if infix.is_some() && paren_func => {
+                paren(self.doc_atom(atom, false, false))
+            },
+            Atom::Call(..) if let Some((op, fixity, a, b)) = infix => {
+                let Atom::Ref(op, _) = op else { unreachable!() };
+                self.doc_operand(a, fixity, true)
+                    .cat(Doc::text(format!(" {}", self.term(op))))
+                    .cat(Doc::Line(" ").cat(self.doc_operand(b, fixity, false)).nest(2))
+                    .group()
+            },
+            Atom::Call(..) => {
+                let mut args = vec![];
+                let mut head = atom;
+                while let Atom::Call(fun, a, _) = self.unref(head) {
+                    if self.infix(head).is_some() {
+                        break;
+                    }
+                    args.push(&**a);
+                    head = fun;
+                }
+                // Fill the lines with term arguments, else one argument per line
+                let fill = args.iter().all(|a| self.unref(a).is_term());
+                let args = args.into_iter().rev().fold(Doc::Nil, |doc, a| {
+                    let arg = Doc::Line(self.sep()).cat(self.doc_atom(a, true, true));
+                    doc.cat(if fill { arg.group() } else { arg })
+                });
+                self.doc_atom(head, false, true)
+                    .cat(args.nest(2))
+                    .group()
+            },
+            func @ Atom::Func(..) => {
+                let (names, body) = self.binders(func);
+                let names = names.iter()
+                    .map(|name| self.term(name))
+                    .collect::<Vec<_>>()
+                    .join(self.sep());
+                Doc::text(names + ".")
+                    .cat(Doc::Line("").cat(self.doc_atom(body, false, false)).nest(2))
+                    .group()
+            },
+        }
+    }
+}
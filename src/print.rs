@@ -0,0 +1,128 @@
+use core::fmt;
+use std::{collections::HashMap, rc::Rc};
+
+use char_classes::any;
+use crate::{parser::Fixity, Atom, Term};
+
+/// Print with the fewest parentheses, curried binders merged into `xyz.`
+///
+/// Result re-parse by [`expr`] to an alpha-equivalent atom,
+/// unless keep the definition names by [`Minimal::refs`]
+///
+/// [`expr`]: crate::expr
+#[derive(Debug, Clone, Copy)]
+pub struct Minimal<'a> {
+    atom: &'a Atom,
+    idents: bool,
+    refs: bool,
+    ops: Option<&'a HashMap<*const Atom, Fixity>>,
+}
+
+impl<'a> Minimal<'a> {
+    pub fn new(atom: &'a Atom) -> Self {
+        Self { atom, idents: false, refs: false, ops: None }
+    }
+
+    /// Print for identifier mode, separate terms by spaces
+    pub fn idents(self, idents: bool) -> Self {
+        Self { idents, ..self }
+    }
+
+    /// Print the names of definitions instead of inlining it
+    pub fn refs(self, refs: bool) -> Self {
+        Self { refs, ..self }
+    }
+
+    /// Print the uses of infix operator definitions as infix, needs [`Self::refs`]
+    pub(crate) fn ops(self, ops: &'a HashMap<*const Atom, Fixity>) -> Self {
+        Self { ops: Some(ops), ..self }
+    }
+
+    pub(crate) fn fixity(&self, atom: &Atom) -> Option<Fixity> {
+        let Atom::Ref(_, body) = atom else { return None };
+        self.ops?.get(&Rc::as_ptr(body)).copied()
+    }
+
+    pub fn atom(&self) -> &'a Atom {
+        self.atom
+    }
+
+    pub(crate) fn unref(&self, atom: &'a Atom) -> &'a Atom {
+        match atom {
+            Atom::Ref(_, sub) if !self.refs => self.unref(sub),
+            _ => atom,
+        }
+    }
+
+    pub(crate) fn term(&self, term: &Term) -> String {
+        let mut chars = term.chars();
+        let single = chars.next().is_some_and(any!("a-zA-Z$_Σ-κμ-ϧ+*/%!-"))
+            && chars.next().is_none();
+        let ident = self.idents
+            && term.starts_with(|ch: char| ch != 'λ' && (ch.is_alphabetic() || any!("_$", ch)))
+            && term.chars().all(|ch| ch.is_alphanumeric() || any!("_$'", ch));
+        if single || ident {
+            term.to_string()
+        } else {
+            format!("`{term}`")
+        }
+    }
+
+    /// Binders of curried functions, and the body
+    pub(crate) fn binders(&self, mut atom: &'a Atom) -> (Vec<&'a Term>, &'a Atom) {
+        let mut names = vec![];
+        while let Atom::Func(name, body, _) = self.unref(atom) {
+            names.push(name);
+            atom = body;
+        }
+        (names, atom)
+    }
+
+    pub(crate) fn sep(&self) -> &'static str {
+        if self.idents { " " } else { "" }
+    }
+}
+
+impl fmt::Display for Minimal<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.pretty(usize::MAX))
+    }
+}
+
+impl Atom {
+    /// Printer with the fewest parentheses
+    pub fn minimal(&self) -> Minimal<'_> {
+        Minimal::new(self)
+    }
+
+    /// Equal up to the renaming of binders, [`Atom::Ref`] are inlined
+    pub fn alpha_eq(&self, other: &Atom) -> bool {
+        fn eq<'a>(
+            a: &'a Atom,
+            b: &'a Atom,
+            env: &mut Vec<(&'a Term, &'a Term)>,
+        ) -> bool {
+            match (a.unref(), b.unref()) {
+                (Atom::Term(a), Atom::Term(b)) => {
+                    let bound = env.iter().rev()
+                        .find(|(x, y)| x == &a || y == &b);
+                    match bound {
+                        Some((x, y)) => x == &a && y == &b,
+                        None => a == b,
+                    }
+                },
+                (Atom::Call(f1, a1, _), Atom::Call(f2, a2, _)) => {
+                    eq(f1, f2, env) && eq(a1, a2, env)
+                },
+                (Atom::Func(x, e1, _), Atom::Func(y, e2, _)) => {
+                    env.push((x, y));
+                    let res = eq(e1, e2, env);
+                    env.pop();
+                    res
+                },
+                _ => false,
+            }
+        }
+        eq(self, other, &mut vec![])
+    }
+}
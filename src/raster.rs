@@ -0,0 +1,211 @@
+use std::io;
+
+use crate::Screen;
+
+/// Options of the raster output
+#[derive(Debug, Clone)]
+pub struct RasterCtx {
+    /// Pixels per cell side
+    pub scale: usize,
+    pub fg: [u8; 3],
+    /// Background color, or transparent for PNG (white for others)
+    pub bg: Option<[u8; 3]>,
+    /// Space around the diagram in pixels
+    pub margin: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RasterFormat {
+    /// Binary bitmap `P4`
+    Pbm,
+    /// Binary pixmap `P6`
+    Ppm,
+    Png,
+}
+
+/// Parse `#rgb`, `#rrggbb` or a basic color name
+pub fn parse_color(s: &str) -> Option<[u8; 3]> {
+    let hex = |s: &str| u8::from_str_radix(s, 16).ok();
+    Some(match s {
+        "black" => [0, 0, 0],
+        "white" => [255, 255, 255],
+        "gray" | "grey" => [128, 128, 128],
+        "red" => [255, 0, 0],
+        "green" => [0, 128, 0],
+        "blue" => [0, 0, 255],
+        "yellow" => [255, 255, 0],
+        "cyan" => [0, 255, 255],
+        "magenta" => [255, 0, 255],
+        _ => {
+            let s = s.strip_prefix('#')?;
+            match s.len() {
+                3 if s.is_ascii() => [hex(&s[..1])? * 17, hex(&s[1..2])? * 17, hex(&s[2..])? * 17],
+                6 if s.is_ascii() => [hex(&s[..2])?, hex(&s[2..4])?, hex(&s[4..])?],
+                _ => return None,
+            }
+        },
+    })
+}
+
+/// Pixels from the top row, `None` is background
+struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Option<[u8; 3]>>,
+}
+
+impl Image {
+    fn rows(&self) -> impl Iterator<Item = &[Option<[u8; 3]>]> {
+        self.pixels.chunks(self.width.max(1)).take(self.height)
+    }
+}
+
+impl Screen {
+    fn image(&self, ctx: &RasterCtx) -> Image {
+        let RasterCtx { scale, fg, margin, .. } = *ctx;
+        let width = self.width() * scale + margin * 2;
+        let height = self.height() * scale + margin * 2;
+        let mut pixels = vec![None; width * height];
+
+        for y in 0..self.height() {
+            for x in (0..self.width()).filter(|&x| self.get(y, x)) {
+                let color = self.cell(y, x).color.unwrap_or(fg);
+                for py in 0..scale {
+                    let start = (margin + y*scale + py) * width + margin + x*scale;
+                    pixels[start..start+scale].fill(Some(color));
+                }
+            }
+        }
+        Image { width, height, pixels }
+    }
+
+    /// Cells as filled squares of the cell colors, labels are not drawn
+    pub fn print_raster<W: io::Write>(
+        &self,
+        ctx: &RasterCtx,
+        format: RasterFormat,
+        w: &mut W,
+    ) -> io::Result<()> {
+        let image = self.image(ctx);
+        match format {
+            RasterFormat::Pbm => pbm(&image, w),
+            RasterFormat::Ppm => ppm(&image, ctx.bg.unwrap_or([255; 3]), w),
+            RasterFormat::Png => png(&image, ctx.bg, w),
+        }
+    }
+}
+
+fn pbm(image: &Image, w: &mut impl io::Write) -> io::Result<()> {
+    write!(w, "P4\n{} {}\n", image.width, image.height)?;
+    for row in image.rows() {
+        let bits = row.chunks(8).map(|bits| {
+            bits.iter().enumerate()
+                .filter(|(_, pixel)| pixel.is_some())
+                .fold(0u8, |byte, (i, _)| byte | 0x80 >> i)
+        });
+        w.write_all(&bits.collect::<Vec<_>>())?;
+    }
+    Ok(())
+}
+
+fn ppm(image: &Image, bg: [u8; 3], w: &mut impl io::Write) -> io::Result<()> {
+    write!(w, "P6\n{} {}\n255\n", image.width, image.height)?;
+    let rgb = image.pixels.iter().flat_map(|pixel| pixel.unwrap_or(bg));
+    w.write_all(&rgb.collect::<Vec<_>>())
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0u32, |crc, &byte| {
+        (0..8).fold(crc ^ u32::from(byte), |crc, _| {
+            if crc & 1 == 1 { crc >> 1 ^ 0xEDB8_8320 } else { crc >> 1 }
+        })
+    })
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (a, b) = bytes.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + u32::from(byte)) % 65521;
+        (a, (b + a) % 65521)
+    });
+    b << 16 | a
+}
+
+/// zlib stream of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xFFFF).peekable();
+    if data.is_empty() {
+        out.extend([1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let len = block.len() as u16;
+        out.push(blocks.peek().is_none().into());
+        out.extend(len.to_le_bytes());
+        out.extend((!len).to_le_bytes());
+        out.extend(block);
+    }
+    out.extend(adler32(data).to_be_bytes());
+    out
+}
+
+fn png_chunk(w: &mut impl io::Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let body = [&kind[..], data].concat();
+    w.write_all(&(data.len() as u32).to_be_bytes())?;
+    w.write_all(&body)?;
+    w.write_all(&crc32(&body).to_be_bytes())
+}
+
+/// Indexed color PNG, background is the index 0
+fn png(image: &Image, bg: Option<[u8; 3]>, w: &mut impl io::Write) -> io::Result<()> {
+    let mut palette = vec![bg.unwrap_or([255; 3])];
+    let mut indexes = Vec::with_capacity(image.pixels.len());
+    for pixel in &image.pixels {
+        let index = match pixel {
+            None => 0,
+            Some(color) => palette.iter().skip(1).position(|c| c == color)
+                .map(|i| i+1)
+                .unwrap_or_else(|| {
+                    palette.push(*color);
+                    palette.len()-1
+                }),
+        };
+        indexes.push(index);
+    }
+
+    let (depth, color_type) = match palette.len() {
+        0..=2 => (1, 3),
+        3..=4 => (2, 3),
+        5..=16 => (4, 3),
+        17..=256 => (8, 3),
+        _ => (8, 2),
+    };
+    let mut data = vec![];
+    for row in indexes.chunks(image.width.max(1)).take(image.height) {
+        data.push(0);
+        if color_type == 2 {
+            data.extend(row.iter().flat_map(|&i| palette[i]));
+            continue;
+        }
+        let per_byte = 8 / depth;
+        data.extend(row.chunks(per_byte).map(|indexes| {
+            indexes.iter().enumerate().fold(0u8, |byte, (i, &index)| {
+                byte | (index as u8) << (8 - depth - i*depth)
+            })
+        }));
+    }
+
+    w.write_all(b"\x89PNG\r\n\x1a\n")?;
+    let mut header = vec![];
+    header.extend((image.width as u32).to_be_bytes());
+    header.extend((image.height as u32).to_be_bytes());
+    header.extend([depth as u8, color_type, 0, 0, 0]);
+    png_chunk(w, b"IHDR", &header)?;
+    if color_type == 3 {
+        png_chunk(w, b"PLTE", &palette.concat())?;
+        if bg.is_none() {
+            png_chunk(w, b"tRNS", &[0])?;
+        }
+    }
+    png_chunk(w, b"IDAT", &zlib_stored(&data))?;
+    png_chunk(w, b"IEND", &[])
+}
@@ -1,38 +1,230 @@
 use core::fmt;
 use std::borrow::Cow;
 
-use to_true::ToTrue;
 use unicode_width::UnicodeWidthStr;
 
-use crate::utils::Sign;
+use crate::{utils::Sign, ansi256};
 
 #[derive(Debug, Default)]
 pub struct Screen {
-    lines: Vec<Vec<bool>>,
+    lines: Vec<Vec<Cell>>,
+    labels: Vec<Label>,
+    segments: Vec<Segment>,
+}
+
+/// Drawn straight line of cells, from `(y, x)` to the right or down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    pub y: usize,
+    pub x: usize,
+    pub len: usize,
+    pub vertical: bool,
+    pub cell: Cell,
+}
+
+/// Part of the diagram drawn on the cell
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CellKind {
+    #[default]
+    Empty,
+    /// Horizontal line of the abstraction
+    Lambda,
+    /// Horizontal line and the output of the application
+    App,
+    /// Vertical line from the abstraction to the variable use
+    Wire,
+    /// Frame of the boxed definition
+    Box,
+    /// Head of the application arrow, next to the function
+    Arrow,
+}
+
+/// Drawn cell, with the ids of the originating node and binder,
+/// see [`GraphCtx::nodes`] and [`GraphCtx::binders`]
+///
+/// [`GraphCtx::nodes`]: crate::GraphCtx::nodes
+/// [`GraphCtx::binders`]: crate::GraphCtx::binders
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Cell {
+    pub kind: CellKind,
+    pub node: usize,
+    pub binder: Option<usize>,
+    /// Color set by [`GraphCtx::paint`], or the foreground
+    ///
+    /// [`GraphCtx::paint`]: crate::GraphCtx::paint
+    pub color: Option<[u8; 3]>,
+}
+
+impl Cell {
+    pub fn new(kind: CellKind, node: usize, binder: Option<usize>) -> Self {
+        Self { kind, node, binder, color: None }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.kind == CellKind::Empty
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Label {
+    y: usize,
+    x: usize,
+    text: String,
 }
 
 pub struct OutputCtx<'a, W> {
     pub writer: W,
     pub has_color: bool,
+    /// Use 24-bit color sequence for the cell colors, else 256 colors
+    pub truecolor: bool,
     pub space: Option<&'a str>,
     pub solid: &'a str,
 }
 
 impl Screen {
-    pub fn bar(&mut self, y: usize, x: usize, downs: usize) {
+    pub fn bar(&mut self, y: usize, x: usize, downs: usize, cell: Cell) {
+        if downs != 0 {
+            self.segments.push(Segment { y, x, len: downs, vertical: true, cell });
+        }
         for i in 0..downs {
-            *self.lines.sign(y+i).sign(x) = true;
+            *self.lines.sign(y+i).sign(x) = cell;
         }
     }
 
-    pub fn line(&mut self, y: usize, x: usize, len: usize) {
+    pub fn line(&mut self, y: usize, x: usize, len: usize, cell: Cell) {
+        if len != 0 {
+            self.segments.push(Segment { y, x, len, vertical: false, cell });
+        }
         for i in 0..len {
-            *self.lines.sign(y).sign(x+i) = true;
+            *self.lines.sign(y).sign(x+i) = cell;
+        }
+    }
+
+    /// Text drawn over the cells, start from `x`
+    pub fn label(&mut self, y: usize, x: usize, text: impl Into<String>) {
+        self.lines.sign(y).sign(x);
+        self.labels.push(Label { y, x, text: text.into() });
+    }
+
+    pub fn height(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn width(&self) -> usize {
+        self.lines.iter().map(Vec::len).max().unwrap_or_default()
+    }
+
+    pub fn get(&self, y: usize, x: usize) -> bool {
+        !self.cell(y, x).is_empty()
+    }
+
+    /// Cell at the position, empty when out of the screen
+    pub fn cell(&self, y: usize, x: usize) -> Cell {
+        self.lines.get(y).and_then(|line| line.get(x)).copied().unwrap_or_default()
+    }
+
+    /// Drawn cells, and the cells of the segments
+    pub(crate) fn cells_mut(&mut self) -> impl Iterator<Item = &mut Cell> {
+        self.lines.iter_mut().flatten().filter(|cell| !cell.is_empty())
+            .chain(self.segments.iter_mut().map(|seg| &mut seg.cell))
+    }
+
+    /// Drawn lines in order, later ones are drawn over the cells
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// Labels as `(y, x, text)`
+    pub fn labels(&self) -> impl Iterator<Item = (usize, usize, &str)> {
+        self.labels.iter().map(|label| (label.y, label.x, &*label.text))
+    }
+
+    /// Text of the label at `(y, x)` clipped to the label cell and the free cells after it,
+    /// so labels do not cover the diagram or each other,
+    /// each cell is `unit` columns, returns the text and the cells taken
+    pub fn label_at(&self, y: usize, x: usize, unit: usize) -> Option<(&str, usize)> {
+        let text = &self.labels.iter().find(|label| (label.y, label.x) == (y, x))?.text;
+        let labelled = |x| self.labels.iter().any(|label| (label.y, label.x) == (y, x));
+        let want = text.width().div_ceil(unit).max(1);
+        let free = 1 + (x+1..x+want).take_while(|&x| !self.get(y, x) && !labelled(x)).count();
+        // a column between the text and the next label
+        let columns = free*unit - usize::from(labelled(x+free));
+        let mut width = 0;
+        let end = text.char_indices()
+            .find(|&(_, ch)| {
+                width += ch.to_string().width();
+                width > columns
+            })
+            .map_or(text.len(), |(i, _)| i);
+        let text = &text[..end];
+        Some((text, text.width().div_ceil(unit).max(1)))
+    }
+
+    /// Place other screen below
+    pub fn append(&mut self, other: &Screen) {
+        let base = self.lines.len();
+        self.lines.extend(other.lines.iter().cloned());
+        self.labels.extend(other.labels.iter().map(|label| Label { y: base + label.y, ..label.clone() }));
+        self.segments.extend(other.segments.iter().map(|seg| Segment { y: base + seg.y, ..*seg }));
+    }
+
+    /// Insert empty columns at the left
+    pub fn indent(&mut self, width: usize) {
+        for line in &mut self.lines {
+            line.splice(0..0, (0..width).map(|_| Cell::default()));
+        }
+        self.labels.iter_mut().for_each(|label| label.x += width);
+        self.segments.iter_mut().for_each(|seg| seg.x += width);
+    }
+
+    /// Column only continues the horizontal lines, and the removal joins nothing
+    fn redundant(&self, x: usize) -> bool {
+        let labelled = self.labels.iter()
+            .any(|label| (label.x..label.x + label.text.width().max(1)).contains(&x));
+        x != 0 && !labelled && (0..self.height()).all(|y| {
+            let (left, right) = (self.get(y, x-1), self.get(y, x+1));
+            if self.get(y, x) {
+                left && right && !(y != 0 && self.get(y-1, x)) && !self.get(y+1, x)
+            } else {
+                !(left && right)
+            }
+        })
+    }
+
+    fn remove_column(&mut self, x: usize) {
+        for line in self.lines.iter_mut().filter(|line| line.len() > x) {
+            line.remove(x);
+        }
+        self.labels.iter_mut().filter(|label| label.x > x).for_each(|label| label.x -= 1);
+        self.segments.retain(|seg| !(seg.vertical && seg.x == x));
+        for seg in &mut self.segments {
+            if seg.x > x {
+                seg.x -= 1;
+            } else if !seg.vertical && seg.x + seg.len > x {
+                seg.len -= 1;
+            }
+        }
+        self.segments.retain(|seg| seg.len != 0);
+    }
+
+    /// Remove the columns only continuing horizontal lines,
+    /// the ends of lines and the gaps between the wires are kept,
+    /// labels keep the columns of their display width
+    pub fn trim_columns(&mut self) {
+        for x in (0..self.width()).rev() {
+            if self.redundant(x) {
+                self.remove_column(x);
+            }
         }
     }
 
     pub fn rev_y(&mut self) {
         self.lines.reverse();
+        let max_y = self.lines.len();
+        self.labels.iter_mut().for_each(|label| label.y = max_y-1 - label.y);
+        self.segments.iter_mut().for_each(|seg| {
+            seg.y = max_y - seg.y - if seg.vertical { seg.len } else { 1 };
+        });
     }
 
     pub fn rev_x(&mut self) {
@@ -42,34 +234,74 @@ impl Screen {
                 line.sign(max_x-1);
                 line.reverse();
             });
+            self.labels.iter_mut().for_each(|label| label.x = max_x-1 - label.x);
+            self.segments.iter_mut().for_each(|seg| {
+                seg.x = max_x - seg.x - if seg.vertical { 1 } else { seg.len };
+            });
         }
     }
 
-    pub fn print<W: fmt::Write>(&self, ctx: &mut OutputCtx<'_, W>) {
+    pub fn print<W: fmt::Write>(&self, ctx: &mut OutputCtx<'_, W>) -> fmt::Result {
         let OutputCtx {
             writer: ref mut w,
             has_color,
+            truecolor,
             space,
             solid,
         } = *ctx;
 
-        let mut color = false;
+        // plain, reverse video, or the background color
+        let mut style = None;
+        let mut restyle = |w: &mut W, new: Option<Option<[u8; 3]>>| -> fmt::Result {
+            if style == new {
+                return Ok(());
+            }
+            match style {
+                Some(None) => write!(w, "\x1b[27m")?,
+                Some(Some(_)) => write!(w, "\x1b[49m")?,
+                None => (),
+            }
+            match new {
+                Some(None) => write!(w, "\x1b[7m")?,
+                Some(Some([r, g, b])) if truecolor => write!(w, "\x1b[48;2;{r};{g};{b}m")?,
+                Some(Some(color)) => write!(w, "\x1b[48;5;{}m", ansi256(color))?,
+                None => (),
+            }
+            style = new;
+            Ok(())
+        };
         let space = space.map(Cow::Borrowed)
             .unwrap_or_else(|| " ".repeat(solid.width()).into());
 
-        for line in &self.lines {
-            for &pos in line {
-                if pos {
-                    if has_color { color.to_true(|| write!(w, "\x1b[7m")); }
-                    write!(w, "{solid}").unwrap();
+        for (y, line) in self.lines.iter().enumerate() {
+            let mut skip = 0;
+            for (x, cell) in line.iter().enumerate() {
+                if skip != 0 {
+                    skip -= 1;
+                    continue;
+                }
+                if let Some((text, cells)) = self.label_at(y, x, space.width().max(1)) {
+                    restyle(w, None)?;
+                    let pad = cells*space.width().max(1) - text.width();
+                    write!(w, "{text}{:pad$}", "")?;
+                    skip = cells - 1;
+                    continue;
+                }
+                if cell.kind == CellKind::Arrow {
+                    if has_color { restyle(w, Some(cell.color))?; }
+                    write!(w, "{:<1$}", "<", solid.width())?;
+                } else if !cell.is_empty() {
+                    if has_color { restyle(w, Some(cell.color))?; }
+                    write!(w, "{solid}")?;
                 } else {
-                    if has_color { color.to_false(|| write!(w, "\x1b[27m")); }
-                    write!(w, "{space}").unwrap();
+                    restyle(w, None)?;
+                    write!(w, "{space}")?;
                 }
             }
 
-            color.to_false(|| write!(w, "\x1b[27m"));
-            writeln!(w).unwrap();
+            restyle(w, None)?;
+            writeln!(w)?;
         }
+        Ok(())
     }
 }
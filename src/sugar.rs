@@ -0,0 +1,121 @@
+use std::str::FromStr;
+
+use crate::{parser::fresh_term, Atom, Span, Term};
+
+/// Encoding of the list and string literals, tuples are `λz.zab` in all encodings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Encoding {
+    /// `[a, b]` is `cn.ca(cbn)`, bytes of string are Church numerals
+    #[default]
+    Church,
+    /// `[a, b]` is `z.za(z.zb(ab.b))` as BLC, bytes of string are lists of 8 bits,
+    /// bit `0` is `ab.a`
+    Scott,
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "church" => Encoding::Church,
+            "scott" => Encoding::Scott,
+            _ => return Err(format!("unknown encoding `{s}`")),
+        })
+    }
+}
+
+/// Build atoms located at the literal
+#[derive(Clone, Copy)]
+struct Build(Span);
+
+impl Build {
+    fn term(self, name: &str) -> Term {
+        Term(name.into(), self.0)
+    }
+
+    fn var(self, term: &Term) -> Atom {
+        Term(term.0.clone(), self.0).into()
+    }
+
+    fn func(self, names: &[&Term], body: Atom) -> Atom {
+        names.iter().rfold(body, |body, &name| {
+            Atom::Func(Term(name.0.clone(), self.0), body.into(), self.0)
+        })
+    }
+
+    fn call(self, fun: Atom, args: impl IntoIterator<Item = Atom>) -> Atom {
+        args.into_iter().fold(fun, |fun, a| Atom::Call(fun.into(), a.into(), self.0))
+    }
+
+    fn fresh(self, base: &str, items: &[Atom]) -> Term {
+        let avoid = items.iter().flat_map(Atom::free_terms).collect::<Vec<_>>();
+        fresh_term(base, &avoid, self.0)
+    }
+}
+
+/// `<a, b>` to `λz.zab`
+pub(crate) fn tuple(items: Vec<Atom>, span: Span) -> Atom {
+    let b = Build(span);
+    let z = b.fresh("z", &items);
+    b.func(&[&z], b.call(b.var(&z), items))
+}
+
+impl Encoding {
+    /// `[a, b]` to list
+    pub(crate) fn list(self, items: Vec<Atom>, span: Span) -> Atom {
+        let b = Build(span);
+        match self {
+            Encoding::Church => {
+                let (c, n) = (b.fresh("c", &items), b.fresh("n", &items));
+                let body = items.into_iter().rfold(b.var(&n), |rest, item| {
+                    b.call(b.var(&c), [item, rest])
+                });
+                b.func(&[&c, &n], body)
+            },
+            Encoding::Scott => {
+                let z = b.fresh("z", &items);
+                let (x, y) = (b.term("a"), b.term("b"));
+                let nil = b.func(&[&x, &y], b.var(&y));
+                items.into_iter().rfold(nil, |rest, item| {
+                    b.func(&[&z], b.call(b.var(&z), [item, rest]))
+                })
+            },
+        }
+    }
+
+    /// `"text"` to list of bytes
+    pub(crate) fn string(self, bytes: &[u8], span: Span) -> Atom {
+        let b = Build(span);
+        let (f, x) = (b.term("f"), b.term("x"));
+        let bytes = bytes.iter().map(|&byte| match self {
+            Encoding::Church => {
+                let n = (0..byte).fold(b.var(&x), |n, _| b.call(b.var(&f), [n]));
+                b.func(&[&f, &x], n)
+            },
+            Encoding::Scott => {
+                let bits = (0..8).rev().map(|i| {
+                    let bit = if byte >> i & 1 == 0 { &f } else { &x };
+                    b.func(&[&f, &x], b.var(bit))
+                });
+                self.list(bits.collect(), span)
+            },
+        });
+        self.list(bytes.collect(), span)
+    }
+}
+
+/// Scott encoded constructor `index` of `count` constructors,
+/// `C := λf1..fm. λc1..cn. ci f1..fm`
+pub(crate) fn constructor(fields: &[Term], index: usize, count: usize, span: Span) -> Atom {
+    let b = Build(span);
+    let mut avoid = fields.to_vec();
+    for _ in 0..count {
+        let c = fresh_term("c", &avoid, span);
+        avoid.push(c);
+    }
+    let cases = avoid[fields.len()..].iter().collect::<Vec<_>>();
+    let body = b.call(b.var(cases[index]), fields.iter().map(|f| b.var(f)));
+    let fields = fields.iter().collect::<Vec<_>>();
+    b.func(&fields, b.func(&cases, body))
+}
@@ -0,0 +1,124 @@
+use core::fmt;
+use std::collections::BTreeMap;
+
+use crate::{CellKind, Screen};
+
+pub struct SvgCtx<'a, W> {
+    pub writer: W,
+    /// Size of a cell in pixels
+    pub cell: f64,
+    /// Width of the lines in pixels
+    pub stroke: f64,
+    pub fg: &'a str,
+    /// Background color, or transparent
+    pub bg: Option<&'a str>,
+    /// Space around the diagram in pixels
+    pub margin: f64,
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+impl Screen {
+    /// Draw the runs of cells as lines through the cell centers, a path per cell color
+    pub fn print_svg<W: fmt::Write>(&self, ctx: &mut SvgCtx<'_, W>) -> fmt::Result {
+        let SvgCtx { writer: ref mut w, cell, stroke, fg, bg, margin } = *ctx;
+        let (width, height) = (self.width(), self.height());
+        let center = |i: usize| margin + (i as f64 + 0.5) * cell;
+        let fg = escape(fg);
+
+        let total = |n: usize| n as f64 * cell + margin * 2.0;
+        let (w_px, h_px) = (total(width), total(height));
+        writeln!(w, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w_px}" height="{h_px}" viewBox="0 0 {w_px} {h_px}">"#)?;
+        if let Some(bg) = bg {
+            writeln!(w, r#"<rect width="100%" height="100%" fill="{}"/>"#, escape(bg))?;
+        }
+
+        // path of each cell color, runs split between the cells of different colors
+        let mut paths = BTreeMap::<Option<[u8; 3]>, String>::new();
+        let mut dots = vec![];
+        let mut run = |cells: &[(usize, usize)], horizontal: bool| {
+            let mut start = 0;
+            while start < cells.len() {
+                let (y, x) = cells[start];
+                let color = self.cell(y, x).color;
+                let len = cells[start..].iter()
+                    .take_while(|&&(y, x)| self.cell(y, x).color == color)
+                    .count();
+                let end = start + len - 1;
+                let pos = |(y, x): (usize, usize)| if horizontal { center(x) } else { center(y) };
+                let from = pos(cells[start]) - if start == 0 { 0.0 } else { cell / 2.0 };
+                let to = pos(cells[end]) + if end+1 == cells.len() { 0.0 } else { cell / 2.0 };
+                let (dir, other) = if horizontal { ('H', center(y)) } else { ('V', center(x)) };
+                let (mx, my) = if horizontal { (from, other) } else { (other, from) };
+                *paths.entry(color).or_default() += &format!("M{mx} {my}{dir}{to}");
+                start = end + 1;
+            }
+        };
+        for y in 0..height {
+            let mut x = 0;
+            while x < width {
+                let len = (x..width).take_while(|&x| self.get(y, x)).count();
+                if len >= 2 {
+                    run(&(x..x+len).map(|x| (y, x)).collect::<Vec<_>>(), true);
+                }
+                x += len.max(1);
+            }
+        }
+        for x in 0..width {
+            let mut y = 0;
+            while y < height {
+                let len = (y..height).take_while(|&y| self.get(y, x)).count();
+                if len >= 2 {
+                    run(&(y..y+len).map(|y| (y, x)).collect::<Vec<_>>(), false);
+                }
+                if len == 1 && !(x != 0 && self.get(y, x-1)) && !self.get(y, x+1) {
+                    dots.push((y, x));
+                }
+                y += len.max(1);
+            }
+        }
+        let paint = |color: Option<[u8; 3]>| match color {
+            Some([r, g, b]) => format!("#{r:02x}{g:02x}{b:02x}"),
+            None => fg.clone(),
+        };
+        for (color, path) in paths {
+            writeln!(w, r#"<path d="{path}" stroke="{}" stroke-width="{stroke}" stroke-linecap="square" fill="none"/>"#, paint(color))?;
+        }
+        for (y, x) in dots {
+            let color = paint(self.cell(y, x).color);
+            let (x, y) = (center(x) - stroke / 2.0, center(y) - stroke / 2.0);
+            writeln!(w, r#"<rect x="{x}" y="{y}" width="{stroke}" height="{stroke}" fill="{color}"/>"#)?;
+        }
+        for (y, x) in (0..height).flat_map(|y| (0..width).map(move |x| (y, x))) {
+            let arrow = self.cell(y, x);
+            if arrow.kind != CellKind::Arrow {
+                continue;
+            }
+            let (x, y, half) = (center(x), center(y), cell / 2.0);
+            writeln!(
+                w,
+                r#"<path d="M{} {y}L{} {}V{}Z" fill="{}"/>"#,
+                x - half,
+                x + half,
+                y - half * 0.6,
+                y + half * 0.6,
+                paint(arrow.color),
+            )?;
+        }
+        for (y, x, _) in self.labels() {
+            // about two monospace chars per cell
+            let Some((text, _)) = self.label_at(y, x, 2) else { continue };
+            writeln!(
+                w,
+                r#"<text x="{}" y="{}" fill="{fg}" font-family="monospace" font-size="{}" dominant-baseline="central">{}</text>"#,
+                margin + x as f64 * cell,
+                center(y),
+                cell * 0.8,
+                escape(text),
+            )?;
+        }
+        writeln!(w, "</svg>")
+    }
+}
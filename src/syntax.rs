@@ -0,0 +1,172 @@
+use std::{rc::Rc, str::FromStr};
+
+use char_classes::any;
+use crate::{Atom, Span, Term};
+
+/// Input notations, all produce the same [`Atom`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Syntax {
+    /// `xy.x`
+    #[default]
+    Default,
+    /// `\x y -> x`
+    Haskell,
+    /// `(lambda (x y) x)`
+    Lisp,
+    /// `λλ2`, indices start from 1
+    DeBruijn,
+}
+
+impl Syntax {
+    /// Guess syntax from the source text, ignoring default syntax comments and strings
+    pub fn detect(s: &str) -> Self {
+        let s = strip_default(s);
+        let s = s.trim_start();
+        if ["(lambda", "(λ ("].iter().any(|lisp| s.contains(lisp)) {
+            Syntax::Lisp
+        } else if s.contains("->") && s.contains('\\') {
+            Syntax::Haskell
+        } else if s.chars().any(|ch| ch.is_ascii_digit())
+            && s.chars().all(|ch| matches!(ch, 'λ' | '\\' | '0'..='9' | '(' | ')')
+                || ch.is_whitespace())
+        {
+            Syntax::DeBruijn
+        } else {
+            Syntax::Default
+        }
+    }
+}
+
+/// Text without `#` comments and string literals of the default syntax
+fn strip_default(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '#' => { chars.by_ref().find(|&ch| ch == '\n'); out.push('\n') },
+            '"' | '`' => { chars.by_ref().find(|&end| end == ch || end == '\n'); },
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "default" => Syntax::Default,
+            "haskell" => Syntax::Haskell,
+            "lisp" => Syntax::Lisp,
+            "debruijn" => Syntax::DeBruijn,
+            _ => return Err(format!("unknown syntax `{s}`")),
+        })
+    }
+}
+
+/// Name of the binder on de Bruijn level
+fn level_name(level: usize) -> Rc<str> {
+    const NAMES: &str = "xyzwvutsrqponmlkjihgfedcba";
+    let ch = NAMES.as_bytes()[level % NAMES.len()] as char;
+    match level / NAMES.len() {
+        0 => ch.to_string().into(),
+        n => format!("{ch}{n}").into(),
+    }
+}
+
+fn funcs(names: Vec<Term>, body: Atom, start: usize) -> Atom {
+    let end = body.span().end;
+    names.into_iter().rfold(body, |body, name| {
+        Atom::Func(name, body.into(), Span::new(start, end))
+    })
+}
+
+fn calls(atoms: Vec<Atom>) -> Atom {
+    atoms.into_iter().reduce(|a, b| {
+        let span = a.span().to(b.span());
+        Atom::Call(a.into(), b.into(), span)
+    }).unwrap()
+}
+
+peg::parser!(grammar parser(base: usize, syntax: Syntax) for str {
+    rule _() = quiet!{([ch if any!(" \t\r\n", ch)] / comment())*}
+    rule comment() = comment_start() [^'\r' | '\n']*
+    rule comment_start()
+        = ";" {? if syntax == Syntax::Lisp { Ok(()) } else { Err("comment") } }
+        / "--" {? if syntax != Syntax::Lisp { Ok(()) } else { Err("comment") } }
+    rule pos() -> usize = p:position!() { base + p }
+    rule lambda() = "λ" / "\\"
+
+    rule ident() -> Term
+        = p:pos()
+          s:quiet!{$([ch if any!("a-zA-Z_", ch)] [ch if any!("a-zA-Z0-9_'", ch)]*)}
+          e:pos()
+        { Term(s.into(), Span::new(p, e)) }
+        / expected!("identifier")
+    rule haskell_lambda() -> Atom
+        = p:pos() lambda() _ names:ident()++_ _ "->" _ body:haskell_expr()
+        { funcs(names, body, p) }
+    rule haskell_atom() -> Atom
+        = t:ident() { t.into() }
+        / "(" _ e:haskell_expr() _ ")" { e }
+    rule haskell_expr() -> Atom
+        = haskell_lambda()
+        / atoms:haskell_atom()++_ tail:(_ l:haskell_lambda() {l})?
+        { calls(atoms.into_iter().chain(tail).collect()) }
+    pub rule haskell() -> Atom = _ e:haskell_expr() _ { e }
+
+    rule symbol() -> Term
+        = p:pos()
+          s:quiet!{$([^ch if any!(" \t\r\n();", ch)]+)}
+          e:pos()
+        { Term(s.into(), Span::new(p, e)) }
+        / expected!("symbol")
+    rule lisp_expr() -> Atom
+        = p:pos() "(" _ ("lambda" / "λ") _ "(" _ names:symbol()**_ _ ")" _
+          body:lisp_expr() _ ")"
+        { funcs(names, body, p) }
+        / "(" _ items:lisp_expr()++_ _ ")" { calls(items) }
+        / t:symbol() { t.into() }
+    pub rule lisp() -> Atom = _ e:lisp_expr() _ { e }
+
+    rule index(binders: &[Term]) -> Atom
+        = p:pos() n:quiet!{$(['0'..='9']+)} e:pos()
+        {?
+            let n = n.parse::<usize>().or(Err("index"))?;
+            let level = binders.len().checked_sub(n).filter(|_| n != 0)
+                .ok_or("bound index")?;
+            Ok(Term(binders[level].0.clone(), Span::new(p, e)).into())
+        }
+        / expected!("index")
+    rule debruijn_lambda(binders: &mut Vec<Term>) -> Atom
+        = p:pos() lambda() e:pos() _
+          ({ binders.push(Term(level_name(binders.len()), Span::new(p, e))); })
+          body:debruijn_expr(binders)?
+        {?
+            let name = binders.pop().unwrap();
+            let body = body.ok_or("expression")?;
+            Ok(funcs(vec![name], body, p))
+        }
+    rule debruijn_atom(binders: &mut Vec<Term>) -> Atom
+        = index(binders)
+        / "(" _ e:debruijn_expr(binders) _ ")" { e }
+    rule debruijn_expr(binders: &mut Vec<Term>) -> Atom
+        = debruijn_lambda(binders)
+        / atoms:debruijn_atom(binders)++_ tail:(_ l:debruijn_lambda(binders) {l})?
+        { calls(atoms.into_iter().chain(tail).collect()) }
+    pub rule debruijn() -> Atom = _ e:debruijn_expr(&mut vec![]) _ { e }
+});
+
+pub(crate) fn parse(
+    syntax: Syntax,
+    s: &str,
+    base: usize,
+) -> Result<Atom, peg::error::ParseError<peg::str::LineCol>> {
+    match syntax {
+        Syntax::Default => unreachable!(),
+        Syntax::Haskell => parser::haskell(s, base, syntax),
+        Syntax::Lisp => parser::lisp(s, base, syntax),
+        Syntax::DeBruijn => parser::debruijn(s, base, syntax),
+    }
+}
@@ -1,15 +1,45 @@
 use core::fmt;
 use std::{borrow::Borrow, cmp, hash, rc::Rc};
 
+/// Byte range in the sources
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    pub fn contains(&self, offset: usize) -> bool {
+        (self.start..self.end).contains(&offset)
+    }
+
+    /// Span cover self and other
+    #[must_use]
+    pub fn to(self, other: Self) -> Self {
+        Self::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+impl std::fmt::Debug for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+/// Name and its span, the span does not take part in the comparison
 #[derive(Clone, Eq)]
-pub struct Term(pub Rc<str>, pub usize);
+pub struct Term(pub Rc<str>, pub Span);
 
 impl std::fmt::Debug for Term {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         if self.0.len() == 1 {
-            write!(f, "{:?}@{}", self.0.chars().next().unwrap(), self.1)
+            write!(f, "{:?}@{}", self.0.chars().next().unwrap(), self.1.start)
         } else {
-            write!(f, "{:?}@{}", self.0, self.1)
+            write!(f, "{:?}@{}", self.0, self.1.start)
         }
     }
 }
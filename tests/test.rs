@@ -1,7 +1,7 @@
 use std::{fmt::Display, fs, path::Path, thread};
 
 use dissimilar::{diff, Chunk};
-use lambda_graph::{expr, GraphCtx, OutputCtx};
+use lambda_graph::{ansi256, expr, parse_color, Atom, Cell, CellKind, Coloring, Diagnostic, Encoding, GraphCtx, JsonCtx, OutputCtx, ParseError, RasterCtx, RasterFormat, Screen, Sources, Span, Style, SvgCtx, Syntax, Term, Warning, PALETTE};
 
 struct Guard<S: Display>(S);
 impl<S: Display> Drop for Guard<S> {
@@ -12,6 +12,21 @@ impl<S: Display> Drop for Guard<S> {
     }
 }
 
+fn render(ctx: &mut GraphCtx, expr: &Atom) -> String {
+    ctx.foo(expr).unwrap();
+
+    let mut octx = OutputCtx {
+        writer: String::new(),
+        has_color: false,
+        truecolor: false,
+        space: Some(" "),
+        solid: "x",
+    };
+    ctx.screen.print(&mut octx).unwrap();
+    octx.writer.truncate(octx.writer.trim_end().len());
+    octx.writer
+}
+
 #[test]
 fn main() {
     let this_file = file!();
@@ -28,23 +43,13 @@ fn main() {
     {
         let _guard = Guard(src);
         let expr = expr(src).expect(src);
-        let ctx = &mut GraphCtx::default();
-        ctx.foo(&expr).unwrap();
-
-        let octx = &mut OutputCtx {
-            writer: String::new(),
-            has_color: false,
-            space: Some(" "),
-            solid: "x",
-        };
-        ctx.screen.print(octx);
-        octx.writer.truncate(octx.writer.trim_end().len());
+        let output = render(&mut GraphCtx::default(), &expr);
 
-        if expected != &octx.writer {
+        if expected != output {
             eprintln!("-- expected --\n{expected}");
-            eprintln!("-- output --\n{}", octx.writer);
+            eprintln!("-- output --\n{output}");
             eprintln!("-- diff --");
-            for diff_chunk in diff(expected, &octx.writer) {
+            for diff_chunk in diff(expected, &output) {
                 match diff_chunk {
                     Chunk::Equal(s) => eprint!("{s}"),
                     Chunk::Delete(s) => eprint!("\x1b[41m{s}\x1b[m\x1b[K"),
@@ -55,3 +60,785 @@ fn main() {
         }
     });
 }
+
+#[test]
+fn keep_defines() {
+    let expr = expr("F := xy.yx; N := (fx.f(fx)); FN(fx.f(f(fx)))").unwrap();
+    assert_eq!(format!("{expr:#}"), "((FN)(λf.(λx.(f(f(fx))))))");
+    assert_eq!(format!("{expr}"), "(((λx.(λy.(yx)))(λf.(λx.(f(fx)))))(λf.(λx.(f(f(fx))))))");
+}
+
+#[test]
+fn boxed_defines() {
+    let atom = expr("K := ab.a; f.K f (K f)").unwrap();
+    let ctx = &mut GraphCtx::default();
+    ctx.boxes.insert("K".into());
+    let output = render(ctx, &atom);
+    assert_eq!(output, "\
+xxxxxxxxxxxxx
+     x     x
+ xxx x xxx x
+ xKx x xKx x
+ xxx x xxx x
+ x   x x   x
+ xxxxx xxxxx
+ x     x
+ xxxxxxx
+ x");
+    assert_eq!(ctx.boxed.len(), 1);
+    assert_eq!(ctx.boxed[0].0, *"K");
+
+    // only the binders of the inputs extend over the box
+    let atom = expr("f.x.(B := f; B)").unwrap();
+    let ctx = &mut GraphCtx::default();
+    ctx.boxes.insert("B".into());
+    let output = render(ctx, &atom);
+    assert_eq!(output, "\
+xxxx
+  x
+xxx
+  x
+ xxx
+ xBx
+ xxx
+ x");
+
+    // the frame fits the display width of a wide name
+    let atom = expr("`漢字` := x.x; `漢字`").unwrap();
+    let ctx = &mut GraphCtx::default();
+    ctx.boxes.insert("漢字".into());
+    ctx.foo(&atom).unwrap();
+    assert_eq!(ctx.screen.width(), 6);
+}
+
+#[test]
+fn recursive_defines() {
+    let rec = expr("rec F := x.F x; F").unwrap();
+    let manual = expr("(f.(x.f(xx))(x.f(xx)))(F.x.Fx)").unwrap();
+    assert_eq!(*rec.unref(), manual);
+
+    let rec = expr("F := a.a; rec F := x.F; F").unwrap();
+    let manual = expr("(f.(x.f(xx))(x.f(xx)))(F.x.F)").unwrap();
+    assert_eq!(*rec.unref(), manual);
+
+    let rec = expr("rec E := n.n O, O := n.n E; E").unwrap();
+    let manual = expr("\
+        A := `x0``x1`.`x0`; B := `x0``x1`.`x1`;
+        (f.(x.f(xx))(x.f(xx)))(p.(EOs.s(n.nO)(n.nE))(pA)(pB)) A
+    ").unwrap();
+    assert_eq!(rec.unref().to_string(), manual.to_string());
+}
+
+#[test]
+fn import_defines() {
+    let dir = Path::new(file!()).parent().unwrap();
+    let path = dir.join("main.lam");
+    let mut sources = Sources::default();
+
+    let expr = sources.parse(Some(&path), r#"import "import.lam"; SKK"#).unwrap();
+    assert_eq!(format!("{expr:#}"), "((SK)K)");
+    assert_eq!(expr.to_string(), "(((λx.(λy.(λz.((xz)(yz)))))(λa.(λb.a)))(λa.(λb.a)))");
+
+    let err = sources.parse(Some(&path), r#"import "import_cycle.lam"; K"#).unwrap_err();
+    assert!(matches!(err, ParseError::ImportCycle(..)), "{err}");
+    assert!(err.source().path.as_ref().unwrap().ends_with("import_cycle.lam"));
+    assert_eq!(err.source().line_column(err.offset()), (2, 1));
+
+    let err = sources.parse(Some(&path), r#"import "missing.lam"; K"#).unwrap_err();
+    assert!(matches!(err, ParseError::Import(..)), "{err}");
+
+    let err = lambda_graph::expr(r#"import "missing.lam"; K"#).unwrap_err();
+    assert!(matches!(err, ParseError::Import(..)), "{err}");
+}
+
+#[test]
+fn recover_diagnostics() {
+    let src = "K := ab.a\nI := x.x;\nF := (ab;\nG := x.x; K";
+    let errors = Sources::default().parse_recover(None, src, 10).unwrap_err();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(Diagnostic::from(&errors[0]).to_string(), "\
+error: expected one of `\"`, `(`, `.`, `;`, `<`, `[`, `case`, term
+ --> <input>:2:3
+  |
+2 | I := x.x;
+  |   ^^");
+    assert_eq!(errors[1].source().line_column(errors[1].offset()), (3, 9));
+
+    // no cascaded error on the blanked statement
+    let path = Path::new(file!()).parent().unwrap().join("main.lam");
+    let errors = Sources::default()
+        .parse_recover(Some(&path), r#"import "import.lam"; SKK x y;"#, 10)
+        .unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].source().line_column(errors[0].offset()), (1, 29));
+}
+
+#[test]
+fn node_spans() {
+    let src = "F := x.xy; a.F(a a)";
+    let expr = expr(src).unwrap();
+    let span = |atom: &Atom| &src[atom.span().start..atom.span().end];
+    assert_eq!(span(&expr), "a.F(a a)");
+    let Atom::Func(_, body, _) = &expr else { panic!() };
+    assert_eq!(span(body), "F(a a)");
+
+    let path = expr.path_at(src.find('y').unwrap());
+    let chain = path.iter()
+        .filter(|atom| atom.is_ref())
+        .map(|atom| span(atom))
+        .collect::<Vec<_>>();
+    assert_eq!(chain, ["F"]);
+    assert_eq!(span(path.last().unwrap()), "y");
+
+    let path = expr.path_at(src.rfind('a').unwrap());
+    assert_eq!(path.iter().map(|atom| span(atom)).collect::<Vec<_>>(), [
+        "a.F(a a)",
+        "F(a a)",
+        "(a a)",
+        "a",
+    ]);
+}
+
+#[test]
+fn parse_warnings() {
+    let mut sources = Sources::default();
+    let expr = sources.parse(None, "F := a.a; G := b.b; F := c.c; x.F(F.F)").unwrap();
+    // the definition is still used under the shadowing binder
+    assert_eq!(expr.to_string(), "(λx.((λc.c)(λF.(λc.c))))");
+
+    let warnings = sources.warnings().iter()
+        .map(|warning| (warning.clone(), warning.span().start))
+        .collect::<Vec<_>>();
+    let term = |name: &str| Term(name.into(), Default::default());
+    assert_eq!(warnings, [
+        (Warning::Redefine(term("F"), term("F")), 20),
+        (Warning::Shadow(term("F"), term("F")), 34),
+        (Warning::Unused(term("G")), 10),
+        (Warning::Unused(term("F")), 0),
+    ]);
+}
+
+#[test]
+fn rec_group_warnings() {
+    let mut sources = Sources::default();
+    sources.parse(None, "rec E := n.n O, O := n.n E; E").unwrap();
+    assert!(sources.warnings().is_empty());
+
+    let mut sources = Sources::default();
+    sources.parse(None, "rec E := n.n E, O := n.n O; E").unwrap();
+    let term = |name: &str| Term(name.into(), Default::default());
+    assert_eq!(sources.warnings(), [Warning::Unused(term("O"))]);
+}
+
+#[test]
+fn input_syntaxes() {
+    let parse = |src: &str| {
+        let mut sources = Sources::default();
+        sources.parse(None, src).unwrap()
+    };
+    let s = expr("xy.x(yy)").unwrap();
+    assert_eq!(parse(r"\x y -> x (y y)"), s);
+    assert_eq!(parse("(lambda (x y) (x (y y)))"), s);
+    assert_eq!(parse("λλ2 (1 1)"), s);
+
+    let y = expr("f.(x.f(xx))(x.f(xx))").unwrap();
+    assert_eq!(parse(r"\f -> (\x -> f (x x)) \x -> f (x x)"), y);
+    assert_eq!(parse("(λ (f) ((λ (x) (f (x x))) (λ (x) (f (x x)))))"), y);
+    assert_eq!(parse("λ(λ2(1 1))(λ2(1 1))").to_string(), "(λx.((λy.(x(yy)))(λy.(x(yy)))))");
+
+    assert_eq!(Syntax::detect("λλ2 1"), Syntax::DeBruijn);
+    assert_eq!(Syntax::detect("λx.x"), Syntax::Default);
+    // default syntax comments and strings are not detected
+    assert_eq!(Syntax::detect("x.x # see (lambda (x) x)"), Syntax::Default);
+    assert_eq!(Syntax::detect("# \\x -> x\nx.x"), Syntax::Default);
+    assert_eq!(parse("x.x # see (lambda (x) x)"), expr("x.x").unwrap());
+
+    // each syntax has its own comment
+    assert_eq!(parse(r"\x -> x -- identity"), expr("x.x").unwrap());
+    assert_eq!(parse("(lambda (x) x) ; identity"), expr("x.x").unwrap());
+    let mut sources = Sources::default();
+    sources.syntax = Some(Syntax::Haskell);
+    assert!(sources.parse(None, r"\x -> x ; identity").is_err());
+    let mut sources = Sources::default();
+    sources.syntax = Some(Syntax::DeBruijn);
+    assert!(sources.parse(None, "λ2").is_err());
+}
+
+#[test]
+fn identifier_mode() {
+    let src = "succ := n f x. f (n f x); zero := f x. x; succ zero";
+    let manual = expr("(nfx.f(nfx))(fx.x)").unwrap();
+
+    let mut sources = Sources::default();
+    sources.idents = true;
+    assert_eq!(sources.parse(None, src).unwrap().to_string(), manual.to_string());
+
+    let src = format!("#pragma idents\n{src}");
+    assert_eq!(Sources::default().parse(None, &src).unwrap().to_string(), manual.to_string());
+    assert!(expr("succ := n f x. f (n f x); succ").is_err());
+}
+
+struct Rng(u64);
+impl Rng {
+    fn below(&mut self, n: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % n as u64) as usize
+    }
+
+    fn atom(&mut self, depth: usize) -> Atom {
+        const NAMES: &[&str] = &["x", "y", "f", "+", "foo", "x1", "Σ"];
+        let name = Term(NAMES[self.below(NAMES.len())].into(), Span::default());
+        match if depth == 0 { 0 } else { self.below(3) } {
+            0 => name.into(),
+            1 => Atom::Call(
+                self.atom(depth-1).into(),
+                self.atom(depth-1).into(),
+                Span::default(),
+            ),
+            _ => Atom::Func(name, self.atom(depth-1).into(), Span::default()),
+        }
+    }
+}
+
+#[test]
+fn minimal_round_trip() {
+    let mut rng = Rng(0x2545F4914F6CDD1D);
+    let mut idents = Sources::default();
+    idents.idents = true;
+
+    for _ in 0..2000 {
+        let atom = rng.atom(6);
+        let src = atom.minimal().to_string();
+        let parsed = expr(&src).unwrap_or_else(|e| panic!("{src}: {e}"));
+        assert!(atom.alpha_eq(&parsed), "{src}\n{atom}\n{parsed}");
+        assert_eq!(parsed.minimal().to_string(), src);
+
+        let src = atom.minimal().idents(true).to_string();
+        let parsed = idents.parse(None, &src).unwrap_or_else(|e| panic!("{src}: {e}"));
+        assert!(atom.alpha_eq(&parsed), "{src}\n{atom}\n{parsed}");
+    }
+
+    let atom = expr("F := xy.yx; N := (fx.f(fx)); FN(fx.f(f(fx)))").unwrap();
+    assert_eq!(atom.minimal().to_string(), "(xy.yx)(fx.f(fx))(fx.f(f(fx)))");
+    assert_eq!(atom.minimal().refs(true).to_string(), "FN(fx.f(f(fx)))");
+    assert!(expr("xy.x").unwrap().alpha_eq(&expr("ab.a").unwrap()));
+    assert!(!expr("xy.x").unwrap().alpha_eq(&expr("ab.b").unwrap()));
+}
+
+#[test]
+fn pretty_width() {
+    let mut rng = Rng(0x9E3779B97F4A7C15);
+    let mut idents = Sources::default();
+    idents.idents = true;
+
+    for width in [0, 8, 20, 40] {
+        for _ in 0..300 {
+            let atom = rng.atom(6);
+            let src = atom.minimal().pretty(width);
+            let parsed = expr(&src).unwrap_or_else(|e| panic!("{src}: {e}"));
+            assert!(atom.alpha_eq(&parsed), "{src}\n{atom}");
+            if atom.minimal().to_string().len() <= width {
+                assert_eq!(src, atom.minimal().to_string());
+            }
+
+            let src = atom.minimal().idents(true).pretty(width);
+            let parsed = idents.parse(None, &src).unwrap_or_else(|e| panic!("{src}: {e}"));
+            assert!(atom.alpha_eq(&parsed), "{src}\n{atom}");
+        }
+    }
+
+    let atom = expr("(abc.a(bc)(cb))(xy.x)(xy.y)(f.f)").unwrap();
+    assert_eq!(atom.minimal().pretty(80), "(abc.a(bc)(cb))(xy.x)(xy.y)(f.f)");
+    assert_eq!(atom.minimal().pretty(20), "\
+(abc.a(bc)(cb))
+  (xy.x)
+  (xy.y)
+  (f.f)");
+    let atom = expr("fx.fxxxxxxxxx").unwrap();
+    assert_eq!(atom.minimal().pretty(8), "\
+fx.
+  fxxxxx
+    xxxx");
+    // the precision of the alternate octal is still the indent level
+    assert_eq!(format!("{:#.8o}", expr("xy.x").unwrap()), "(λx.λy.x)");
+    assert_eq!(format!("{:#.0o}", expr("x.x").unwrap()), "(λx.\n  x\n)");
+}
+
+#[test]
+fn format_source() {
+    let src = "\
+# booleans
+
+
+T := ab.a;  # true
+`F`:=ab.b;
+N := p.p F T;
+rec E := n.n(x.O x)T, O := n.(E n);
+I := (x.x)
+  # inner
+  ;
+L := abcde.a(bc)(cde)(edcba)(abcde);
+N(E (I T))
+# end
+";
+    let out = Sources::default().format(None, src, 30).unwrap();
+    assert_eq!(out, "\
+# booleans
+
+T := ab.a; # true
+F := ab.b;
+N := p.pFT;
+rec E := n.n(x.Ox)T,
+    O := n.En;
+I := (x.x)
+  # inner
+  ;
+L := abcde.
+    a(bc)(cde)(edcba)(abcde);
+N(E(IT))
+# end
+");
+    assert_eq!(Sources::default().format(None, &out, 30).unwrap(), out);
+    assert_eq!(expr(&out).unwrap().to_string(), expr(src).unwrap().to_string());
+}
+
+#[test]
+fn format_as_written() {
+    // nested definitions, inner comments and literals are kept
+    let src = "\
+A := (B := x.x; B B);
+C := x. # body
+  x;
+D := [a, \"hi\"];
+A   C
+";
+    let out = Sources::default().format(None, src, 80).unwrap();
+    assert_eq!(out, "\
+A := (B := x.x; B B);
+C := x. # body
+  x;
+D := [a, \"hi\"];
+AC
+");
+    assert_eq!(Sources::default().format(None, &out, 80).unwrap(), out);
+}
+
+#[test]
+fn literal_sugar() {
+    let parse = |encoding, src: &str| {
+        let mut sources = Sources::default();
+        sources.encoding = encoding;
+        sources.parse(None, src).unwrap()
+    };
+    let church = |src| parse(Encoding::Church, src);
+    let scott = |src| parse(Encoding::Scott, src);
+
+    assert!(church("[a, b.b, c]").alpha_eq(&expr("xy.xa(x(b.b)(xcy))").unwrap()));
+    assert!(church("[]").alpha_eq(&expr("xy.y").unwrap()));
+    assert!(church("[n, c]").alpha_eq(&expr("xy.xn(xcy)").unwrap()));
+    assert!(scott("[a, b]").alpha_eq(&expr("z.za(z.zb(xy.y))").unwrap()));
+    assert!(church("<a, z>").alpha_eq(&expr("x.xaz").unwrap()));
+
+    assert!(church(r#""\n""#).alpha_eq(&expr("cn.c(fx.f(f(f(f(f(f(f(f(f(fx))))))))))n").unwrap()));
+    let bits = "0,1,0,0,0,0,0,1".replace('0', "ab.a").replace('1', "ab.b");
+    assert!(scott(r#""A""#).alpha_eq(&scott(&format!("[[{bits}]]"))));
+    assert!(expr(r#""a"#).is_err());
+}
+
+#[test]
+fn data_declarations() {
+    let mut sources = Sources::default();
+    sources.idents = true;
+    let mut parse = |src: &str| sources.parse(None, src);
+
+    let just = parse("data Maybe = Nothing | Just a; Just").unwrap();
+    assert!(just.alpha_eq(&expr("a n j.j a").unwrap()));
+    let case = parse("data M = N | J a; f. case f of { J x -> x; N -> J f; }").unwrap();
+    assert!(case.alpha_eq(&expr("f.f ((anj.ja) f) (x.x)").unwrap()));
+    let case = parse("data B = T | F; x. case x of { F -> x; T -> T }").unwrap();
+    assert!(case.alpha_eq(&expr("x.x(tf.t)x").unwrap()));
+
+    assert!(parse("data M = N | J a; f. case f of { J x -> x }").is_err());
+    assert!(parse("data M = N | J a; f. case f of { J x y -> x; N -> N }").is_err());
+    assert!(parse("data B = T | F; data M = N | J a; f. case f of { J x -> x; T -> N }").is_err());
+    assert!(parse("z := a.a; case z of { z -> a }").is_err());
+
+    // reported at the invalid arm
+    let src = "data M = N | J a; f. case f of { N -> f; J x y -> x }";
+    let err = parse(src).unwrap_err();
+    assert!(matches!(err, ParseError::Case(..)), "{err}");
+    assert_eq!(err.offset(), src.find("J x y").unwrap());
+    assert_eq!(err.to_string(), "<input>:1:42: `J` has 1 field, the arm binds 2");
+    let err = parse("data M = N | J a; f. case f of { N -> f }").unwrap_err();
+    assert_eq!(err.to_string(), "<input>:1:22: missing arm of `J`");
+}
+
+#[test]
+fn infix_operators() {
+    let defs = "infixl 6 + := ab.a; infixl 7 * := ab.b; infixr 5 - := xy.x; infix 4 % := xy.y;";
+    let parse = |src: &str| expr(&format!("{defs} {src}")).map(|e| format!("{e:#}"));
+
+    assert_eq!(parse("a + b * c + d").unwrap(), "((+((+a)((*b)c)))d)");
+    assert_eq!(parse("a - b - c * d").unwrap(), "((-a)((-b)((*c)d)))");
+    assert_eq!(parse("f a + (+) b c").unwrap(), "((+(fa))((+b)c))");
+    assert_eq!(parse("a % (b % c)").unwrap(), "((%a)((%b)c))");
+    assert!(parse("a % b % c").is_err());
+    assert_eq!(parse("a + b - c + d").unwrap(), "((-((+a)b))((+c)d))");
+    assert!(parse("a + - b").is_err());
+
+    let src = format!("{defs}\n(a+b) * c + d - e - ((+) f g h) - (+) - (x.x)+(y.y)\n");
+    let out = Sources::default().format(None, &src, 80).unwrap();
+    assert!(out.ends_with("\n(a + b) * c + d - e - (f + g)h - (+) - (x.x) + (y.y)\n"), "{out}");
+    assert_eq!(expr(&out).unwrap().to_string(), expr(&src).unwrap().to_string());
+}
+
+#[test]
+fn svg_output() {
+    let mut ctx = GraphCtx::default();
+    ctx.foo(&expr("x.x").unwrap()).unwrap();
+    let mut svg = SvgCtx {
+        writer: String::new(),
+        cell: 10.0,
+        stroke: 2.0,
+        fg: "red",
+        bg: None,
+        margin: 5.0,
+    };
+    ctx.screen.print_svg(&mut svg).unwrap();
+    assert_eq!(svg.writer, r#"<svg xmlns="http://www.w3.org/2000/svg" width="40" height="30" viewBox="0 0 40 30">
+<path d="M10 10H30M20 10V20" stroke="red" stroke-width="2" stroke-linecap="square" fill="none"/>
+</svg>
+"#);
+
+    let mut ctx = GraphCtx::default();
+    ctx.boxes.insert("<K>".into());
+    ctx.foo(&expr("`<K>` := ab.a; xy.`<K>`xy").unwrap()).unwrap();
+    svg.writer.clear();
+    ctx.screen.print_svg(&mut svg).unwrap();
+    assert!(svg.writer.contains(">&lt;K&gt;</text>"), "{}", svg.writer);
+}
+
+#[test]
+fn raster_output() {
+    let mut ctx = GraphCtx::default();
+    ctx.foo(&expr("x.x").unwrap()).unwrap();
+    let raster = RasterCtx { scale: 1, fg: [0; 3], bg: None, margin: 0 };
+
+    let mut pbm = vec![];
+    ctx.screen.print_raster(&raster, RasterFormat::Pbm, &mut pbm).unwrap();
+    assert_eq!(pbm, b"P4\n3 2\n\xe0\x40");
+
+    let mut ppm = vec![];
+    let red = RasterCtx { scale: 2, fg: parse_color("#f00").unwrap(), bg: parse_color("white"), ..raster };
+    ctx.screen.print_raster(&red, RasterFormat::Ppm, &mut ppm).unwrap();
+    assert!(ppm.starts_with(b"P6\n6 4\n255\n\xff\x00\x00"));
+    assert_eq!(ppm.len(), 11 + 6*4*3);
+
+    let mut png = vec![];
+    ctx.screen.print_raster(&raster, RasterFormat::Png, &mut png).unwrap();
+    assert!(png.starts_with(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\0\x03\0\0\0\x02\x01\x03"));
+    let idat = b"IDAT\x78\x01\x01\x04\x00\xfb\xff\x00\xe0\x00\x40";
+    assert!(png.windows(idat.len()).any(|w| w == idat));
+    assert!(png.windows(4).any(|w| w == b"tRNS"));
+    assert!(png.ends_with(b"IEND\xae\x42\x60\x82"));
+}
+
+#[test]
+fn box_drawing() {
+    let mut ctx = GraphCtx::default();
+    ctx.boxes.insert("K".into());
+    ctx.foo(&expr("K := ab.a; f.K f (K f)").unwrap()).unwrap();
+    let mut out = String::new();
+    ctx.screen.print_box(&mut out).unwrap();
+    assert_eq!(out, "\
+╶─────────┬───────────┬─╴
+          │           │
+  ┌───┐   │   ┌───┐   │
+  │ K │   │   │ K │   │
+  ├───┘   │   ├───┘   │
+  │       │   │       │
+  ├───────┘   ├───────┘
+  │           │
+  ├───────────┘
+  ╵
+");
+}
+
+#[test]
+fn dense_output() {
+    let mut ctx = GraphCtx::default();
+    ctx.foo(&expr("x.x").unwrap()).unwrap();
+    let mut half = String::new();
+    ctx.screen.print_half(&mut half).unwrap();
+    assert_eq!(half, "▀█▀\n");
+    let mut braille = String::new();
+    ctx.screen.print_braille(&mut braille).unwrap();
+    assert_eq!(braille, "⠙⠁\n");
+
+    let mut ctx = GraphCtx::default();
+    ctx.foo(&expr("fx.f(f(fx))").unwrap()).unwrap();
+    let mut braille = String::new();
+    ctx.screen.print_braille(&mut braille).unwrap();
+    assert_eq!(braille.lines().count(), ctx.screen.height().div_ceil(4));
+}
+
+#[test]
+fn span_equality() {
+    assert_ne!(Span::new(0, 5), Span::new(2, 3));
+    // atoms and terms compare without the spans
+    assert_eq!(expr("x.x").unwrap(), expr(" x.x").unwrap());
+    assert_ne!(expr("x.x").unwrap().span(), expr(" x.x").unwrap().span());
+}
+
+#[test]
+fn typed_cells() {
+    let mut ctx = GraphCtx::default();
+    ctx.foo(&expr("x.x x").unwrap()).unwrap();
+    let screen = &ctx.screen;
+    let lambda = screen.cell(0, 0);
+    assert_eq!(lambda.kind, CellKind::Lambda);
+    assert_eq!(&*ctx.binders[lambda.binder.unwrap()].name.0, "x");
+    assert_eq!(ctx.nodes[lambda.node].span, Span::new(0, 5));
+    assert_eq!(ctx.nodes[lambda.node].depth, 0);
+
+    let wire = screen.cell(1, 1);
+    assert_eq!(wire.kind, CellKind::Wire);
+    assert_eq!(wire.binder, lambda.binder);
+    assert_eq!(ctx.nodes[wire.node].span, Span::new(2, 3));
+    assert_eq!(screen.cell(2, 1).kind, CellKind::App);
+    assert!(screen.cell(1, 2).is_empty());
+    assert!(screen.cell(100, 100).is_empty());
+}
+
+#[test]
+fn coloring() {
+    let mut ctx = GraphCtx::default();
+    ctx.foo(&expr("fx.f(fx)").unwrap()).unwrap();
+    ctx.paint(Coloring::Binder, &PALETTE);
+    let screen = &ctx.screen;
+    for y in 0..screen.height() {
+        for x in 0..screen.width() {
+            let cell = screen.cell(y, x);
+            match cell.kind {
+                CellKind::Lambda | CellKind::Wire => {
+                    assert_eq!(cell.color, Some(PALETTE[cell.binder.unwrap()]));
+                },
+                _ => assert_eq!(cell.color, None),
+            }
+        }
+    }
+    assert_ne!(screen.cell(0, 0).color, screen.cell(2, 0).color);
+
+    ctx.paint(Coloring::BinderDepth, &[[1, 2, 3]]);
+    assert_eq!(ctx.screen.cell(0, 0).color, Some([1, 2, 3]));
+    ctx.paint(Coloring::NodeDepth, &PALETTE);
+    assert_eq!(ctx.screen.cell(0, 0).color, Some(PALETTE[0]));
+
+    let mut octx = OutputCtx {
+        writer: String::new(),
+        has_color: true,
+        truecolor: true,
+        space: Some(" "),
+        solid: "x",
+    };
+    ctx.screen.print(&mut octx).unwrap();
+    assert!(octx.writer.starts_with("\x1b[48;2;31;119;180mxxxxxxx\x1b[49m\n"), "{:?}", octx.writer);
+    assert_eq!(ansi256([0, 0, 0]), 16);
+    assert_eq!(ansi256([255, 255, 255]), 231);
+
+    let mut svg = SvgCtx { writer: String::new(), cell: 8.0, stroke: 2.0, fg: "black", bg: None, margin: 0.0 };
+    ctx.screen.print_svg(&mut svg).unwrap();
+    assert!(svg.writer.contains(r##"stroke="#1f77b4""##));
+    assert!(!svg.writer.contains(r#"stroke="black""#));
+}
+
+#[test]
+fn name_labels() {
+    let mut ctx = GraphCtx::default();
+    ctx.wire_labels = true;
+    ctx.foo(&expr("fx.f(fx)").unwrap()).unwrap();
+    ctx.label_binders();
+    let mut octx = OutputCtx {
+        writer: String::new(),
+        has_color: false,
+        truecolor: false,
+        space: Some(" "),
+        solid: "x",
+    };
+    ctx.screen.print(&mut octx).unwrap();
+    let lines = octx.writer.lines().map(str::trim_end).collect::<Vec<_>>();
+    assert_eq!(lines, [
+        "f xxxxxxx",
+        "   x x",
+        "x xxxxxxx",
+        "   f f x",
+        "   x xxx",
+        "   x x",
+        "   xxx",
+        "   x",
+    ]);
+
+    // labels of adjacent wires are clipped to the free cells
+    let mut ctx = GraphCtx::default();
+    ctx.wire_labels = true;
+    ctx.foo(&expr("`foo`.`bar`. `foo` `bar` `foo`").unwrap()).unwrap();
+    let mut octx = OutputCtx {
+        writer: String::new(),
+        has_color: false,
+        truecolor: false,
+        space: Some(" "),
+        solid: "x",
+    };
+    ctx.screen.print(&mut octx).unwrap();
+    let lines = octx.writer.lines().map(str::trim_end).collect::<Vec<_>>();
+    assert_eq!(lines[3], " f b foo");
+    assert_eq!(ctx.screen.label_at(3, 1, 2), Some(("foo", 2)));
+}
+
+#[test]
+fn geometry_json() {
+    let mut ctx = GraphCtx::default();
+    ctx.foo(&expr("x.x x").unwrap()).unwrap();
+    let segments = ctx.screen.segments();
+    assert_eq!(segments.len(), 5);
+    let lambda = segments.last().unwrap();
+    assert_eq!((lambda.y, lambda.x, lambda.len, lambda.vertical), (0, 0, 5, false));
+    assert_eq!(lambda.cell.kind, CellKind::Lambda);
+    assert!(segments.iter().all(|seg| (0..seg.len).all(|i| {
+        let (y, x) = if seg.vertical { (seg.y+i, seg.x) } else { (seg.y, seg.x+i) };
+        ctx.screen.get(y, x)
+    })));
+
+    let mut json = JsonCtx { writer: String::new(), nodes: &ctx.nodes, binders: &ctx.binders };
+    ctx.screen.print_json(&mut json).unwrap();
+    let json = json.writer;
+    assert!(json.starts_with(r#"{"width":5,"height":4,"segments":["#));
+    assert!(json.contains(r#"{"kind":"lambda","from":[0,0],"to":[4,0],"node":0,"binder":0,"name":"x","color":null}"#));
+    assert!(json.contains(r#"{"kind":"app","from":[1,2],"to":[3,2],"node":1,"binder":null,"name":null,"color":null}"#));
+    assert!(json.contains(r#"],"binders":[
+{"name":"x","node":0,"depth":0}
+],"labels":[
+]}"#));
+}
+
+#[test]
+fn diagram_styles() {
+    let expr = expr("fx.f(f x)").unwrap();
+    let styled = |style| {
+        let mut ctx = GraphCtx::default();
+        ctx.style = style;
+        render(&mut ctx, &expr)
+            .lines()
+            .map(str::trim_end)
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    assert_eq!(styled(Style::Standard), render(&mut GraphCtx::default(), &expr));
+    assert_eq!(styled(Style::Alternative), "\
+xxxxxxx
+ x x
+xxxxxxx
+ x xxx
+ x x
+ xxx
+ x");
+    // wires of the second binder start a row below the merged line
+    assert_eq!(styled(Style::Merged), "\
+xxxxxxx
+ x x
+ x x x
+ x xxx
+ x x
+ xxx
+ x");
+    assert_eq!(styled(Style::Arrows), "\
+xxxxxxx
+ x x
+xxxxxxx
+ x x x
+ x x<x
+ x x
+ x<x
+ x");
+    assert_eq!("merged".parse(), Ok(Style::Merged));
+
+    // only the direct body of an abstraction is merged
+    let styled = |style, src| {
+        let mut ctx = GraphCtx::default();
+        ctx.style = style;
+        render(&mut ctx, &lambda_graph::expr(src).unwrap())
+    };
+    assert_eq!(styled(Style::Merged, "x.(y.y) x"), styled(Style::Standard, "x.(y.y) x"));
+    assert_eq!(styled(Style::Merged, "F := y.y; x.F"), styled(Style::Merged, "xy.y"));
+    assert_ne!(styled(Style::Merged, "xy.y"), styled(Style::Standard, "xy.y"));
+    assert_eq!(styled(Style::Merged, "ab.ab"), "\
+xxxxx
+ x
+ x x
+ xxx
+ x");
+}
+
+#[test]
+fn trim_columns() {
+    let mut ctx = GraphCtx::default();
+    ctx.call_extra_unit = 2;
+    ctx.func_extra_unit = Some(1);
+    ctx.boxes.insert("K".into());
+    ctx.foo(&expr("K := ab.a; f.(x.f(x x))(x.K f (x x))").unwrap()).unwrap();
+
+    // wires connected to the lines of their binders, and the runs of cells
+    let shape = |screen: &Screen| {
+        let mut wires = screen.segments().iter()
+            .filter(|seg| seg.cell.kind == CellKind::Wire)
+            .map(|seg| {
+                let top = screen.cell(seg.y, seg.x);
+                assert_eq!((top.kind, top.binder), (CellKind::Lambda, seg.cell.binder));
+                (seg.cell.binder, seg.cell.node)
+            })
+            .collect::<Vec<_>>();
+        wires.sort_unstable();
+        let runs = |cells: Vec<bool>| {
+            cells.split(|&set| !set).filter(|run| !run.is_empty()).count()
+        };
+        let rows = (0..screen.height())
+            .map(|y| runs((0..screen.width()).map(|x| screen.get(y, x)).collect()))
+            .collect::<Vec<_>>();
+        let mut bars = (0..screen.width())
+            .flat_map(|x| {
+                let column = (0..screen.height()).map(|y| screen.get(y, x)).collect::<Vec<_>>();
+                column.split(|&set| !set).map(<[_]>::len).filter(|&len| len > 1).collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        bars.sort_unstable();
+        (wires, rows, bars)
+    };
+    let before = shape(&ctx.screen);
+    let width = ctx.screen.width();
+    ctx.screen.trim_columns();
+    assert!(ctx.screen.width() < width, "{} < {width}", ctx.screen.width());
+    assert_eq!(shape(&ctx.screen), before);
+    assert!(ctx.screen.labels().any(|(_, _, text)| text == "K"));
+
+    assert!(ctx.screen.segments().iter().all(|seg| (0..seg.len).all(|i| {
+        let (y, x) = if seg.vertical { (seg.y+i, seg.x) } else { (seg.y, seg.x+i) };
+        ctx.screen.get(y, x)
+    })));
+
+    let trimmed = ctx.screen.width();
+    ctx.screen.trim_columns();
+    assert_eq!(ctx.screen.width(), trimmed);
+}
+
+#[test]
+fn trim_wide_labels() {
+    let mut screen = Screen::default();
+    screen.line(0, 0, 8, Cell::new(CellKind::Lambda, 0, Some(0)));
+    screen.label(1, 1, "日本");
+    screen.trim_columns();
+    // the label keeps its display width, the columns after it are removed
+    assert_eq!(screen.width(), 6);
+    assert_eq!(screen.labels().collect::<Vec<_>>(), [(1, 1, "日本")]);
+}